@@ -0,0 +1,259 @@
+// Generates the `Language` enum and its lookup tables from languages.toml
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+};
+
+#[derive(serde::Deserialize)]
+struct Database {
+    language: Vec<LanguageDefinition>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LanguageDefinition {
+    variant: String,
+    name: String,
+    #[serde(default)]
+    extensions: Vec<String>,
+    #[serde(default)]
+    filenames: Vec<String>,
+    #[serde(default)]
+    interpreters: Vec<String>,
+    #[serde(default)]
+    modes: Vec<String>,
+    #[serde(default)]
+    line_comments: Vec<String>,
+    block_comment: Option<[String; 2]>,
+    #[serde(default)]
+    binary: bool,
+}
+
+// Maps every key to the languages declaring it, in declaration order
+fn index<'a>(
+    languages: &'a [LanguageDefinition],
+    keys: impl Fn(&'a LanguageDefinition) -> &'a [String],
+) -> BTreeMap<&'a str, Vec<&'a str>> {
+    let mut index = BTreeMap::<_, Vec<_>>::new();
+    for language in languages {
+        for key in keys(language) {
+            index
+                .entry(key.as_str())
+                .or_default()
+                .push(language.variant.as_str());
+        }
+    }
+    index
+}
+
+fn write_lookup(
+    out: &mut String,
+    name: &str,
+    index: &BTreeMap<&str, Vec<&str>>,
+) -> std::fmt::Result {
+    writeln!(out, "    pub fn {name}(key: &str) -> Option<Language> {{")?;
+    writeln!(out, "        let language = match key {{")?;
+    for (key, variants) in index {
+        writeln!(out, "            {key:?} => Language::{},", variants[0])?;
+    }
+    writeln!(out, "            _ => return None,")?;
+    writeln!(out, "        }};")?;
+    writeln!(out, "        Some(language)")?;
+    writeln!(out, "    }}")
+}
+
+fn assert_unique(what: &str, index: &BTreeMap<&str, Vec<&str>>) {
+    for (key, variants) in index {
+        assert!(
+            variants.len() == 1,
+            "{what} {key:?} is claimed by several languages: {variants:?}"
+        );
+    }
+}
+
+fn generate(languages: &[LanguageDefinition]) -> Result<String, std::fmt::Error> {
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        "#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]"
+    )?;
+    writeln!(out, "pub enum Language {{")?;
+    for language in languages {
+        writeln!(out, "    {},", language.variant)?;
+    }
+    writeln!(
+        out,
+        "    // Registered at runtime, see `registry::register_language`"
+    )?;
+    writeln!(out, "    Custom(&'static str),")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+
+    writeln!(out, "impl Language {{")?;
+    writeln!(out, "    // Built-in languages")?;
+    writeln!(out, "    pub const ALL: &'static [Language] = &[")?;
+    for language in languages {
+        writeln!(out, "        Language::{},", language.variant)?;
+    }
+    writeln!(out, "    ];")?;
+    writeln!(out)?;
+
+    writeln!(out, "    pub fn name(self) -> &'static str {{")?;
+    writeln!(out, "        match self {{")?;
+    for language in languages {
+        writeln!(
+            out,
+            "            Language::{} => {:?},",
+            language.variant, language.name
+        )?;
+    }
+    writeln!(out, "            Language::Custom(name) => name,")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+
+    // Stable identifier, unlike the displayed name
+    writeln!(out, "    pub fn id(self) -> &'static str {{")?;
+    writeln!(out, "        match self {{")?;
+    for language in languages {
+        writeln!(out, "            Language::{0} => {0:?},", language.variant)?;
+    }
+    writeln!(out, "            Language::Custom(name) => name,")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+
+    writeln!(
+        out,
+        "    pub fn line_comments(self) -> &'static [&'static str] {{"
+    )?;
+    writeln!(out, "        match self {{")?;
+    for language in languages {
+        writeln!(
+            out,
+            "            Language::{} => &{:?},",
+            language.variant, language.line_comments
+        )?;
+    }
+    writeln!(out, "            Language::Custom(_) => &[],")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+
+    writeln!(
+        out,
+        "    pub fn block_comment(self) -> Option<[&'static str; 2]> {{"
+    )?;
+    writeln!(out, "        match self {{")?;
+    for language in languages {
+        writeln!(
+            out,
+            "            Language::{} => {:?},",
+            language.variant, language.block_comment
+        )?;
+    }
+    writeln!(out, "            Language::Custom(_) => None,")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+
+    writeln!(out, "    pub fn is_binary(self) -> bool {{")?;
+    writeln!(out, "        match self {{")?;
+    for language in languages {
+        writeln!(
+            out,
+            "            Language::{} => {},",
+            language.variant, language.binary
+        )?;
+    }
+    writeln!(out, "            Language::Custom(_) => false,")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+
+    writeln!(out, "    pub fn from_id(id: &str) -> Option<Language> {{")?;
+    writeln!(out, "        let language = match id {{")?;
+    for language in languages {
+        writeln!(out, "            {0:?} => Language::{0},", language.variant)?;
+    }
+    writeln!(out, "            _ => return None,")?;
+    writeln!(out, "        }};")?;
+    writeln!(out, "        Some(language)")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+
+    let extensions = index(languages, |l| &l.extensions);
+    write_lookup(&mut out, "from_extension", &extensions)?;
+    writeln!(out)?;
+
+    writeln!(
+        out,
+        "    pub fn is_ambiguous_extension(extension: &str) -> bool {{"
+    )?;
+    let ambiguous: Vec<_> = extensions
+        .iter()
+        .filter(|(_, variants)| variants.len() > 1)
+        .map(|(extension, _)| format!("{extension:?}"))
+        .collect();
+    writeln!(
+        out,
+        "        matches!(extension, {})",
+        ambiguous.join(" | ")
+    )?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+
+    let filenames = index(languages, |l| &l.filenames);
+    assert_unique("filename", &filenames);
+    write_lookup(&mut out, "from_filename", &filenames)?;
+    writeln!(out)?;
+
+    let interpreters = index(languages, |l| &l.interpreters);
+    assert_unique("interpreter", &interpreters);
+    write_lookup(&mut out, "from_interpreter", &interpreters)?;
+    writeln!(out)?;
+
+    let modes = index(languages, |l| &l.modes);
+    assert_unique("mode", &modes);
+    write_lookup(&mut out, "from_mode", &modes)?;
+    writeln!(out, "}}")?;
+
+    Ok(out)
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=languages.toml");
+
+    let database = std::fs::read_to_string("languages.toml").expect("languages.toml is readable");
+    let database: Database = toml::from_str(&database).expect("languages.toml is valid");
+
+    let mut variants = HashSet::new();
+    for language in &database.language {
+        assert!(
+            variants.insert(&language.variant),
+            "language {} is declared twice",
+            language.variant
+        );
+        for extension in &language.extensions {
+            assert!(
+                *extension == extension.to_lowercase(),
+                "extension {extension:?} of {} must be lowercase",
+                language.variant
+            );
+        }
+        for mode in &language.modes {
+            assert!(
+                *mode == mode.to_lowercase(),
+                "mode {mode:?} of {} must be lowercase",
+                language.variant
+            );
+        }
+    }
+
+    let out = generate(&database.language).expect("writing to a String never fails");
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    std::fs::write(std::path::Path::new(&out_dir).join("languages.rs"), out)
+        .expect("OUT_DIR is writable");
+}
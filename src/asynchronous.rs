@@ -0,0 +1,130 @@
+// The same API as the crate root, for tokio users
+
+use std::path::{Path, PathBuf};
+
+use tokio::io::AsyncBufReadExt;
+
+use crate::{
+    count::{files, CountOptions, Report},
+    diagnostic::Diagnostic,
+    file_info::{identify_for_count, FileInfo, LineCounter},
+    language::Language,
+    line_kind::LineKindEstimator,
+};
+
+pub async fn file_info_from_path(
+    file: &Path,
+    debug: bool,
+) -> std::io::Result<(FileInfo, Language)> {
+    let options = CountOptions {
+        debug,
+        ..Default::default()
+    };
+    file_info_with_diagnostics(file, &options, &mut vec![]).await
+}
+
+async fn file_info_with_diagnostics(
+    file: &Path,
+    options: &CountOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> std::io::Result<(FileInfo, Language)> {
+    let debug = options.debug;
+    // Identification peeks into the file with blocking reads
+    let path = file.to_path_buf();
+    let (language, identify_diagnostics) = tokio::task::spawn_blocking(move || {
+        let mut diagnostics = vec![];
+        let language = identify_for_count(&path, debug, &mut diagnostics);
+        (language, diagnostics)
+    })
+    .await?;
+    diagnostics.extend(identify_diagnostics);
+
+    let file_infos = match LineCounter::for_file(file, language, options, diagnostics) {
+        Some(counter) => {
+            let f = tokio::fs::File::open(file).await?;
+            count_lines(tokio::io::BufReader::new(f), counter)
+                .await?
+                .finish_file(file, language, diagnostics)
+        }
+        None => FileInfo::new_non_text(),
+    };
+
+    tracing::trace!(?file, ?language, ?file_infos, "Generated file info");
+    Ok((file_infos, language))
+}
+
+async fn count_lines(
+    mut reader: impl tokio::io::AsyncBufRead + Unpin,
+    mut counter: LineCounter,
+) -> std::io::Result<LineCounter> {
+    let mut line_buf = Vec::new();
+    while reader.read_until(b'\n', &mut line_buf).await? != 0 {
+        if !counter.count(&line_buf) {
+            break;
+        }
+        line_buf.clear();
+    }
+    Ok(counter)
+}
+
+pub async fn gen_file_info_from_reader(
+    reader: impl tokio::io::AsyncBufRead + Unpin,
+    line_kind_estimator: Option<Box<dyn LineKindEstimator + Send>>,
+) -> std::io::Result<FileInfo> {
+    let Some(line_kind_estimator) = line_kind_estimator else {
+        return Ok(FileInfo::new_non_text());
+    };
+
+    Ok(count_lines(reader, LineCounter::new(line_kind_estimator))
+        .await?
+        .finish())
+}
+
+pub async fn gen_file_info(
+    file: &Path,
+    line_kind_estimator: Option<Box<dyn LineKindEstimator + Send>>,
+) -> std::io::Result<FileInfo> {
+    if line_kind_estimator.is_none() {
+        return Ok(FileInfo::new_non_text());
+    }
+
+    let f = tokio::fs::File::open(file).await?;
+    gen_file_info_from_reader(tokio::io::BufReader::new(f), line_kind_estimator).await
+}
+
+pub async fn count_paths(
+    paths: impl IntoIterator<Item = impl AsRef<Path>>,
+    options: &CountOptions,
+) -> Report {
+    let paths: Vec<PathBuf> = paths
+        .into_iter()
+        .map(|path| path.as_ref().to_path_buf())
+        .collect();
+    // The walker does blocking IO too, keep it out of the runtime
+    let walk_options = options.clone();
+    let walked =
+        tokio::task::spawn_blocking(move || files(paths, &walk_options).collect::<Vec<_>>()).await;
+
+    let mut report = Report::default();
+    // A walker that panicked found nothing it could tell about
+    let files = match walked {
+        Ok(files) => files,
+        Err(err) => {
+            report
+                .errors
+                .push(anyhow::Error::new(err).context("while walking the directories"));
+            vec![]
+        }
+    };
+    for path in files {
+        match path {
+            Ok(path) => {
+                let result =
+                    file_info_with_diagnostics(&path, options, &mut report.diagnostics).await;
+                report.push(path, result);
+            }
+            Err(err) => report.errors.push(err),
+        }
+    }
+    report
+}
@@ -0,0 +1,99 @@
+use std::{fmt::Display, path::Path};
+
+use loc::{CountOptions, FileInfo, Language};
+
+use crate::{
+    errors::ErrorCollector,
+    select_columns,
+    sort::{info_number, SortArgs, SortKey, Sortable},
+    table::{Table, TableDescriptor, TableDescriptorBuilder, TableFormat, TableWrapper},
+    Column, TableFileValue,
+};
+
+#[derive(Debug, Clone)]
+pub enum TableAuthorKey {
+    Author(String),
+    Total,
+}
+
+impl Display for TableAuthorKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TableAuthorKey::Author(author) => write!(f, "{}", author),
+            TableAuthorKey::Total => write!(f, "Total"),
+        }
+    }
+}
+
+impl Sortable for ((String, Language), FileInfo) {
+    fn name(&self) -> String {
+        self.0 .0.clone()
+    }
+
+    fn language(&self) -> String {
+        self.0 .1.to_string()
+    }
+
+    fn number(&self, key: SortKey) -> Option<f64> {
+        info_number(&self.1, key)
+    }
+}
+
+struct TableAuthor;
+impl Table for TableAuthor {
+    type Key = TableAuthorKey;
+    type Value = TableFileValue;
+    fn describe() -> TableDescriptor<Self::Value, Self::Key> {
+        TableDescriptorBuilder::column_key_with_format(
+            "Author",
+            TableFormat::Left,
+            |x: &TableAuthorKey| x,
+        )
+        .column_with_format("Language", TableFormat::Left, |value: &TableFileValue| {
+            value.display_language()
+        })
+        .column("Code", |value: &TableFileValue| value.display_code())
+        .column("Comments", |value: &TableFileValue| {
+            value.display_comments()
+        })
+        .column("Empty", |value: &TableFileValue| value.display_empty())
+        .column("Total", |value: &TableFileValue| value.display_total())
+        .column_with_format(
+            "File count",
+            TableFormat::Right,
+            |value: &TableFileValue| value.display_file_count(),
+        )
+        .build()
+    }
+}
+
+pub fn run(
+    path: &Path,
+    rev: Option<&str>,
+    sort: &SortArgs,
+    columns: Option<&[Column]>,
+    options: &CountOptions,
+    errors: &mut ErrorCollector,
+) -> anyhow::Result<()> {
+    let authorship = loc::git::blame(path, rev.unwrap_or("HEAD"), options)?;
+    errors.collect(&authorship.errors)?;
+
+    let mut rows: Vec<_> = authorship.by_author.into_iter().collect();
+    sort.sort(&mut rows)?;
+
+    let rows_iter = rows
+        .into_iter()
+        .map(|((author, language), info)| {
+            (
+                TableAuthorKey::Author(author),
+                TableFileValue::File { info, language },
+            )
+        })
+        .chain(std::iter::once((
+            TableAuthorKey::Total,
+            TableFileValue::Total(authorship.total),
+        )));
+    let descriptor = select_columns(TableAuthor::describe(), columns)?;
+    println!("{}", TableWrapper::with_descriptor(descriptor, rows_iter));
+    errors.finish()
+}
@@ -0,0 +1,369 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use loc::{count_paths, CountOptions, FileInfo, Language, Summary};
+
+use crate::{
+    compare,
+    errors::{ErrorCollector, PolicyViolation, UsageError},
+    table::{Table, TableDescriptor, TableDescriptorBuilder, TableFormat, TableWrapper},
+};
+
+#[derive(clap::Args)]
+pub struct CheckArgs {
+    #[arg(default_value = ".")]
+    path: PathBuf,
+    /// The limits to enforce, as TOML
+    #[arg(short, long, value_name = "FILE", required_unless_present = "ratchet")]
+    policy: Option<PathBuf>,
+    /// Fail when a file or directory grew past the size recorded in this JSON file, and record
+    /// the ones that shrank. The file is created on the first run.
+    #[arg(long, value_name = "FILE")]
+    ratchet: Option<PathBuf>,
+    /// Only count the files tracked in the git index
+    #[arg(long)]
+    git_tracked: bool,
+}
+
+// Every limit is optional, languages are given by id, like `Javascript`
+#[derive(serde::Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    // Total lines of any single file
+    pub max_lines_per_file: Option<usize>,
+    // Code lines of the files directly in any directory
+    pub max_code_per_directory: Option<usize>,
+    // Comments / (code + comments), over all the files of the language
+    #[serde(default)]
+    pub min_comment_ratio: BTreeMap<Language, f64>,
+    #[serde(default)]
+    pub forbidden_languages: Vec<Language>,
+}
+
+pub fn load_policy(path: &Path) -> anyhow::Result<Policy> {
+    let policy = std::fs::read_to_string(path)
+        .with_context(|| UsageError(format!("while reading {}", path.display())))?;
+    toml::from_str(&policy).with_context(|| UsageError(format!("while parsing {}", path.display())))
+}
+
+pub struct Violation {
+    pub rule: &'static str,
+    pub value: String,
+    pub limit: String,
+}
+
+fn comment_ratio(info: &FileInfo) -> f64 {
+    match info.code + info.comments {
+        0 => 0.,
+        lines => info.comments as f64 / lines as f64,
+    }
+}
+
+// The code of the files directly in each directory, relative to the counted root
+pub fn code_per_directory(summary: &Summary) -> BTreeMap<PathBuf, usize> {
+    let mut directories = BTreeMap::<PathBuf, usize>::new();
+    for entry in &summary.files {
+        let directory = match entry.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        *directories.entry(directory).or_default() += entry.info.code;
+    }
+    directories
+}
+
+// The violations of `policy`, by file, directory or language
+pub fn violations(policy: &Policy, summary: &Summary) -> Vec<(String, Violation)> {
+    let mut violations = vec![];
+
+    for language in &policy.forbidden_languages {
+        for entry in summary.files.iter().filter(|x| x.language == *language) {
+            violations.push((
+                entry.path.display().to_string(),
+                Violation {
+                    rule: "forbidden language",
+                    value: language.to_string(),
+                    limit: "-".to_string(),
+                },
+            ));
+        }
+    }
+
+    if let Some(max_lines) = policy.max_lines_per_file {
+        for entry in summary.files.iter().filter(|x| x.info.total > max_lines) {
+            violations.push((
+                entry.path.display().to_string(),
+                Violation {
+                    rule: "lines per file",
+                    value: entry.info.total.to_string(),
+                    limit: max_lines.to_string(),
+                },
+            ));
+        }
+    }
+
+    if let Some(max_code) = policy.max_code_per_directory {
+        for (directory, code) in code_per_directory(summary) {
+            if code > max_code {
+                violations.push((
+                    directory.display().to_string(),
+                    Violation {
+                        rule: "code per directory",
+                        value: code.to_string(),
+                        limit: max_code.to_string(),
+                    },
+                ));
+            }
+        }
+    }
+
+    for (language, min_ratio) in &policy.min_comment_ratio {
+        // Nothing to comment, nothing to enforce
+        let Some(info) = summary.languages.get(language) else {
+            continue;
+        };
+        let ratio = comment_ratio(info);
+        if ratio < *min_ratio {
+            violations.push((
+                language.to_string(),
+                Violation {
+                    rule: "comment ratio",
+                    value: format!("{ratio:.3}"),
+                    limit: format!("{min_ratio:.3}"),
+                },
+            ));
+        }
+    }
+
+    violations
+}
+
+// The largest sizes allowed so far: total lines per file and code per directory, like the policy
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct Ratchet {
+    pub files: BTreeMap<PathBuf, usize>,
+    pub directories: BTreeMap<PathBuf, usize>,
+}
+
+impl Ratchet {
+    pub fn of(summary: &Summary) -> Self {
+        Self {
+            files: summary
+                .files
+                .iter()
+                .map(|entry| (entry.path.clone(), entry.info.total))
+                .collect(),
+            directories: code_per_directory(summary),
+        }
+    }
+
+    // The violations of the recorded sizes, and the tightened ratchet. Sizes that grew are left
+    // as they were, the others follow the summary, new files and directories included.
+    pub fn advance(&self, summary: &Summary) -> (Vec<(String, Violation)>, Self) {
+        fn advance_sizes(
+            rule: &'static str,
+            recorded: &BTreeMap<PathBuf, usize>,
+            current: BTreeMap<PathBuf, usize>,
+            violations: &mut Vec<(String, Violation)>,
+        ) -> BTreeMap<PathBuf, usize> {
+            current
+                .into_iter()
+                .map(|(path, size)| match recorded.get(&path) {
+                    Some(&max) if size > max => {
+                        violations.push((
+                            path.display().to_string(),
+                            Violation {
+                                rule,
+                                value: size.to_string(),
+                                limit: max.to_string(),
+                            },
+                        ));
+                        (path, max)
+                    }
+                    _ => (path, size),
+                })
+                .collect()
+        }
+
+        let current = Self::of(summary);
+        let mut violations = vec![];
+        let files = advance_sizes(
+            "ratcheted lines per file",
+            &self.files,
+            current.files,
+            &mut violations,
+        );
+        let directories = advance_sizes(
+            "ratcheted code per directory",
+            &self.directories,
+            current.directories,
+            &mut violations,
+        );
+        (violations, Self { files, directories })
+    }
+}
+
+pub fn load_ratchet(path: &Path) -> anyhow::Result<Option<Ratchet>> {
+    let json = match std::fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| UsageError(format!("while reading {}", path.display())))
+        }
+    };
+    serde_json::from_str(&json)
+        .map(Some)
+        .with_context(|| UsageError(format!("while parsing {}", path.display())))
+}
+
+pub fn save_ratchet(ratchet: &Ratchet, path: &Path) -> anyhow::Result<()> {
+    let json = serde_json::to_string_pretty(ratchet)?;
+    std::fs::write(path, json).with_context(|| format!("while saving {}", path.display()))
+}
+
+struct TableViolations;
+impl Table for TableViolations {
+    type Key = String;
+    type Value = Violation;
+    fn describe() -> TableDescriptor<Self::Value, Self::Key> {
+        TableDescriptorBuilder::column_key_with_format("Where", TableFormat::Left, |x: &String| x)
+            .column_with_format("Rule", TableFormat::Left, |x: &Violation| &x.rule)
+            .column_with_format("Value", TableFormat::Right, |x: &Violation| &x.value)
+            .column_with_format("Limit", TableFormat::Right, |x: &Violation| &x.limit)
+            .build()
+    }
+}
+
+pub fn print_violations(violations: Vec<(String, Violation)>) -> anyhow::Result<()> {
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let count = violations.len();
+    println!(
+        "{}",
+        TableWrapper::new::<TableViolations>(violations.into_iter())
+    );
+    anyhow::bail!(PolicyViolation(count))
+}
+
+pub fn run(
+    args: CheckArgs,
+    options: &CountOptions,
+    errors: &mut ErrorCollector,
+) -> anyhow::Result<()> {
+    let policy = args.policy.as_deref().map(load_policy).transpose()?;
+    let options = CountOptions {
+        git_tracked: args.git_tracked,
+        ..options.clone()
+    };
+    let report = count_paths([&args.path], &options);
+    errors.collect(&report.errors)?;
+    // The ratchet is not advanced from a partial count, a file that could not be counted would
+    // lose its recorded size and come back as a new one
+    if args.ratchet.is_some() {
+        errors.finish()?;
+    }
+
+    let mut summary = compare::snapshot(&report, &args.path);
+    // The ratchet changes with every run, it would never stop growing
+    if let Some(ratchet) = args.ratchet.as_deref().and_then(|x| x.canonicalize().ok()) {
+        summary.files.retain(|entry| {
+            args.path.join(&entry.path).canonicalize().ok().as_ref() != Some(&ratchet)
+        });
+    }
+    let mut found = policy
+        .map(|policy| violations(&policy, &summary))
+        .unwrap_or_default();
+    if let Some(path) = &args.ratchet {
+        let recorded = load_ratchet(path)?;
+        let (ratchet_violations, ratchet) = match &recorded {
+            Some(recorded) => recorded.advance(&summary),
+            None => (vec![], Ratchet::of(&summary)),
+        };
+        if recorded.as_ref() != Some(&ratchet) {
+            save_ratchet(&ratchet, path)?;
+        }
+        found.extend(ratchet_violations);
+    }
+    print_violations(found)?;
+    errors.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use loc::FileEntry;
+
+    #[test]
+    fn policy_languages_are_checked() {
+        let policy: Policy = toml::from_str(
+            "forbidden_languages = [\"Javascript\"]\n[min_comment_ratio]\nRust = 0.1\n",
+        )
+        .unwrap();
+        assert_eq!(policy.forbidden_languages, [Language::Javascript]);
+        assert_eq!(policy.min_comment_ratio.get(&Language::Rust), Some(&0.1));
+
+        let misspelled = toml::from_str::<Policy>("forbidden_languages = [\"Javscript\"]");
+        assert!(misspelled.unwrap_err().to_string().contains("Javscript"));
+        let misspelled = toml::from_str::<Policy>("[min_comment_ratio]\nRuts = 0.1\n");
+        assert!(misspelled.unwrap_err().to_string().contains("Ruts"));
+    }
+
+    fn summary(files: &[(&str, usize)]) -> Summary {
+        let files = files
+            .iter()
+            .map(|&(path, lines)| FileEntry {
+                path: PathBuf::from(path),
+                language: Language::Rust,
+                info: FileInfo {
+                    total: lines,
+                    code: lines,
+                    ..FileInfo::new()
+                },
+            })
+            .collect();
+        Summary {
+            files,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn ratchet_advance() {
+        let recorded = Ratchet::of(&summary(&[("a.rs", 10), ("src/b.rs", 10), ("gone.rs", 5)]));
+        let (violations, advanced) =
+            recorded.advance(&summary(&[("a.rs", 12), ("src/b.rs", 4), ("new.rs", 7)]));
+
+        // a.rs grew, so did the root directory
+        let found: Vec<_> = violations
+            .iter()
+            .map(|(path, violation)| {
+                (
+                    path.as_str(),
+                    violation.value.as_str(),
+                    violation.limit.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(found, [("a.rs", "12", "10"), (".", "19", "15")]);
+
+        let files: Vec<_> = advanced.files.into_iter().collect();
+        assert_eq!(
+            files,
+            [
+                (PathBuf::from("a.rs"), 10),
+                (PathBuf::from("new.rs"), 7),
+                (PathBuf::from("src/b.rs"), 4),
+            ]
+        );
+        let directories: Vec<_> = advanced.directories.into_iter().collect();
+        assert_eq!(
+            directories,
+            [(PathBuf::from("."), 15), (PathBuf::from("src"), 4)]
+        );
+    }
+}
@@ -0,0 +1,332 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use loc::{FileEntry, FileInfo, Language, Report, Summary};
+
+use crate::{
+    errors::UsageError,
+    select_columns,
+    sort::{SortArgs, SortKey, Sortable},
+    table::{Table, TableDescriptor, TableDescriptorBuilder, TableFormat, TableWrapper},
+    Column, Mode, TableFileKey, TableKey,
+};
+
+// The summary of a report, with paths relative to the counted root so that two checkouts of the
+// same project can be compared
+pub fn snapshot(report: &Report, root: &Path) -> Summary {
+    let mut summary = report.summary();
+    for entry in &mut summary.files {
+        if let Ok(path) = entry.path.strip_prefix(root) {
+            entry.path = path.to_path_buf();
+        }
+    }
+    summary.files.sort_by(|a, b| a.path.cmp(&b.path));
+    summary
+}
+
+pub fn save_baseline(summary: &Summary, path: &Path) -> anyhow::Result<()> {
+    let json = serde_json::to_string_pretty(summary)?;
+    std::fs::write(path, json).with_context(|| format!("while saving {}", path.display()))
+}
+
+pub fn load_baseline(path: &Path) -> anyhow::Result<Summary> {
+    let json = std::fs::read_to_string(path)
+        .with_context(|| UsageError(format!("while reading {}", path.display())))?;
+    serde_json::from_str(&json)
+        .with_context(|| UsageError(format!("while parsing {}", path.display())))
+}
+
+// A signed count, always displayed with its sign
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Change(pub isize);
+
+impl Change {
+    fn between(old: usize, new: usize) -> Self {
+        Change(new as isize - old as isize)
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0 > 0 {
+            write!(f, "+{}", self.0)
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileInfoDelta {
+    pub code: Change,
+    pub comments: Change,
+    pub empty: Change,
+    pub total: Change,
+    pub file_count: Change,
+}
+
+impl FileInfoDelta {
+    pub fn between(old: &FileInfo, new: &FileInfo) -> Self {
+        Self {
+            code: Change::between(old.code, new.code),
+            comments: Change::between(old.comments, new.comments),
+            empty: Change::between(old.empty, new.empty),
+            total: Change::between(old.total, new.total),
+            file_count: Change::between(old.file_count, new.file_count),
+        }
+    }
+
+    pub fn is_unchanged(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+pub fn language_deltas(old: &Summary, new: &Summary) -> Vec<(Language, FileInfoDelta)> {
+    let languages: BTreeSet<_> = old.languages.keys().chain(new.languages.keys()).collect();
+    let empty = FileInfo::default();
+    languages
+        .into_iter()
+        .map(|language| {
+            let old = old.languages.get(language).unwrap_or(&empty);
+            let new = new.languages.get(language).unwrap_or(&empty);
+            (*language, FileInfoDelta::between(old, new))
+        })
+        .filter(|(_, delta)| !delta.is_unchanged())
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum FileStatus {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl Display for FileStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            FileStatus::Added => "added",
+            FileStatus::Removed => "removed",
+            FileStatus::Changed => "changed",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+pub struct FileDelta {
+    pub status: FileStatus,
+    pub language: Language,
+    pub delta: FileInfoDelta,
+}
+
+pub fn file_deltas(old: &Summary, new: &Summary) -> Vec<(PathBuf, FileDelta)> {
+    fn by_path(summary: &Summary) -> BTreeMap<&Path, &FileEntry> {
+        summary
+            .files
+            .iter()
+            .map(|entry| (entry.path.as_path(), entry))
+            .collect()
+    }
+    let old = by_path(old);
+    let new = by_path(new);
+    let paths: BTreeSet<_> = old.keys().chain(new.keys()).copied().collect();
+
+    // A file that does not exist counts as nothing, not even as a file
+    let nothing = FileInfo {
+        file_count: 0,
+        ..FileInfo::new()
+    };
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let (status, language, old_info, new_info) = match (old.get(path), new.get(path)) {
+                (Some(old), Some(new)) => (FileStatus::Changed, new.language, &old.info, &new.info),
+                (None, Some(new)) => (FileStatus::Added, new.language, &nothing, &new.info),
+                (Some(old), None) => (FileStatus::Removed, old.language, &old.info, &nothing),
+                (None, None) => return None,
+            };
+            let delta = FileInfoDelta::between(old_info, new_info);
+            (!delta.is_unchanged()).then(|| {
+                (
+                    path.to_path_buf(),
+                    FileDelta {
+                        status,
+                        language,
+                        delta,
+                    },
+                )
+            })
+        })
+        .collect()
+}
+
+impl FileInfoDelta {
+    // Only the counts have a change, ratios do not
+    fn number(&self, key: SortKey) -> Option<f64> {
+        let change = match key {
+            SortKey::Code => self.code,
+            SortKey::Comments => self.comments,
+            SortKey::Empty => self.empty,
+            SortKey::Total => self.total,
+            SortKey::Files => self.file_count,
+            _ => return None,
+        };
+        Some(change.0 as f64)
+    }
+}
+
+impl Sortable for (Language, FileInfoDelta) {
+    fn name(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn language(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn number(&self, key: SortKey) -> Option<f64> {
+        self.1.number(key)
+    }
+}
+
+impl Sortable for (PathBuf, FileDelta) {
+    fn name(&self) -> String {
+        self.0.display().to_string()
+    }
+
+    fn language(&self) -> String {
+        self.1.language.to_string()
+    }
+
+    fn number(&self, key: SortKey) -> Option<f64> {
+        self.1.delta.number(key)
+    }
+}
+
+pub struct TableLanguageDelta;
+impl Table for TableLanguageDelta {
+    type Key = TableKey;
+    type Value = FileInfoDelta;
+    fn describe() -> TableDescriptor<Self::Value, Self::Key> {
+        TableDescriptorBuilder::column_key("Language", |x: &TableKey| x)
+            .column_with_format("Code", TableFormat::Right, |x: &FileInfoDelta| &x.code)
+            .column_with_format("Comments", TableFormat::Right, |x: &FileInfoDelta| {
+                &x.comments
+            })
+            .column_with_format("Empty", TableFormat::Right, |x: &FileInfoDelta| &x.empty)
+            .column_with_format("Total", TableFormat::Right, |x: &FileInfoDelta| &x.total)
+            .column_with_format("File count", TableFormat::Right, |x: &FileInfoDelta| {
+                &x.file_count
+            })
+            .build()
+    }
+}
+
+pub enum TableFileDeltaValue {
+    File(FileDelta),
+    Total(FileInfoDelta),
+}
+
+impl TableFileDeltaValue {
+    fn delta(&self) -> &FileInfoDelta {
+        match self {
+            TableFileDeltaValue::File(file) => &file.delta,
+            TableFileDeltaValue::Total(delta) => delta,
+        }
+    }
+
+    fn display_status(&self) -> &dyn Display {
+        match self {
+            TableFileDeltaValue::File(file) => &file.status,
+            TableFileDeltaValue::Total(_) => &"-",
+        }
+    }
+
+    fn display_language(&self) -> &dyn Display {
+        match self {
+            TableFileDeltaValue::File(file) => &file.language,
+            TableFileDeltaValue::Total(_) => &"-",
+        }
+    }
+}
+
+pub struct TableFileDelta;
+impl Table for TableFileDelta {
+    type Key = TableFileKey;
+    type Value = TableFileDeltaValue;
+    fn describe() -> TableDescriptor<Self::Value, Self::Key> {
+        TableDescriptorBuilder::column_key_with_format(
+            "File",
+            TableFormat::Left,
+            |x: &TableFileKey| x,
+        )
+        .column_with_format("Code", TableFormat::Right, |x: &TableFileDeltaValue| {
+            &x.delta().code
+        })
+        .column_with_format("Comments", TableFormat::Right, |x: &TableFileDeltaValue| {
+            &x.delta().comments
+        })
+        .column_with_format("Empty", TableFormat::Right, |x: &TableFileDeltaValue| {
+            &x.delta().empty
+        })
+        .column_with_format("Total", TableFormat::Right, |x: &TableFileDeltaValue| {
+            &x.delta().total
+        })
+        .column_with_format("Status", TableFormat::Left, |x: &TableFileDeltaValue| {
+            x.display_status()
+        })
+        .column_with_format("Language", TableFormat::Left, |x: &TableFileDeltaValue| {
+            x.display_language()
+        })
+        .build()
+    }
+}
+
+pub fn print_comparison(
+    old: &Summary,
+    new: &Summary,
+    mode: Mode,
+    sort: &SortArgs,
+    columns: Option<&[Column]>,
+) -> anyhow::Result<()> {
+    let total = FileInfoDelta::between(&old.total, &new.total);
+    match mode {
+        Mode::Language => {
+            let mut rows = language_deltas(old, new);
+            sort.sort(&mut rows)?;
+
+            let rows_iter = rows
+                .into_iter()
+                .map(|(language, delta)| (TableKey::Language(language), delta))
+                .chain(std::iter::once((TableKey::Total, total)));
+            let descriptor = select_columns(TableLanguageDelta::describe(), columns)?;
+            println!("{}", TableWrapper::with_descriptor(descriptor, rows_iter));
+        }
+        Mode::File => {
+            let mut rows = file_deltas(old, new);
+            sort.sort(&mut rows)?;
+
+            let rows_iter = rows
+                .into_iter()
+                .map(|(path, file)| {
+                    (
+                        TableFileKey::Path(path.display().to_string()),
+                        TableFileDeltaValue::File(file),
+                    )
+                })
+                .chain(std::iter::once((
+                    TableFileKey::Total,
+                    TableFileDeltaValue::Total(total),
+                )));
+            let descriptor = select_columns(TableFileDelta::describe(), columns)?;
+            println!("{}", TableWrapper::with_descriptor(descriptor, rows_iter));
+        }
+        Mode::Author => unreachable!("author mode is rejected with --compare"),
+    }
+    Ok(())
+}
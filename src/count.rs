@@ -0,0 +1,167 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::{
+    diagnostic::{Diagnostic, DiagnosticKind},
+    file_info::{file_info_with_diagnostics, FileInfo},
+    language::Language,
+};
+
+#[derive(Debug, Default, Clone)]
+pub struct CountOptions {
+    // Report unidentified files
+    pub debug: bool,
+    // Count the files in the git index instead of walking the disk, for paths inside a work tree.
+    // Without the `git` feature, or outside of a repository, the disk is walked anyway.
+    pub git_tracked: bool,
+    // Recount the files ending in an unterminated block comment as if the language had no block
+    // comments, instead of counting everything after the opening as comments
+    pub recount_unterminated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileEntry {
+    pub path: PathBuf,
+    pub language: Language,
+    pub info: FileInfo,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub files: Vec<FileEntry>,
+    // Files that could not be counted, they are not part of `files`
+    pub errors: Vec<anyhow::Error>,
+    // What was odd about the files found on disk, counted or not
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+// Everything a `Report` found, in a stable order. Unlike the report, it can be saved and loaded
+// back with the `serde` feature.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Summary {
+    pub total: FileInfo,
+    pub languages: BTreeMap<Language, FileInfo>,
+    // Sorted by path
+    pub files: Vec<FileEntry>,
+}
+
+impl Report {
+    pub fn total(&self) -> FileInfo {
+        let mut total = FileInfo::default();
+        for entry in &self.files {
+            total.merge_with(&entry.info);
+        }
+        total
+    }
+
+    pub fn by_language(&self) -> HashMap<Language, FileInfo> {
+        let mut by_language = HashMap::<Language, FileInfo>::new();
+        for entry in &self.files {
+            by_language
+                .entry(entry.language)
+                .or_default()
+                .merge_with(&entry.info);
+        }
+        by_language
+    }
+
+    pub fn summary(&self) -> Summary {
+        let mut files = self.files.clone();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Summary {
+            total: self.total(),
+            languages: self.by_language().into_iter().collect(),
+            files,
+        }
+    }
+
+    pub(crate) fn push(&mut self, path: PathBuf, result: std::io::Result<(FileInfo, Language)>) {
+        if let Err(err) = &result {
+            self.diagnostics.push(
+                Diagnostic::new(&path, DiagnosticKind::Unreadable).with_detail(err.to_string()),
+            );
+        }
+        match result.with_context(|| format!("while getting file infos from {}", path.display())) {
+            Ok((info, language)) => self.files.push(FileEntry {
+                path,
+                language,
+                info,
+            }),
+            Err(err) => self.errors.push(err),
+        }
+    }
+}
+
+// Files found under the given paths, honouring ignore files and skipping hidden ones
+pub(crate) fn walk(
+    paths: impl IntoIterator<Item = impl AsRef<Path>>,
+) -> impl Iterator<Item = Result<PathBuf, ignore::Error>> {
+    let mut paths = paths.into_iter();
+    let walker = paths.next().map(|first| {
+        let mut builder = ignore::WalkBuilder::new(first);
+        for path in paths {
+            builder.add(path);
+        }
+        builder.hidden(true).build()
+    });
+
+    walker
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| match entry {
+            Ok(entry) if entry.path().is_dir() => None,
+            Ok(entry) => Some(Ok(entry.into_path())),
+            Err(err) => Some(Err(err)),
+        })
+}
+
+// The files to count under every given path, see `CountOptions::git_tracked`
+pub(crate) fn files(
+    paths: impl IntoIterator<Item = impl AsRef<Path>>,
+    options: &CountOptions,
+) -> impl Iterator<Item = anyhow::Result<PathBuf>> {
+    let paths: Vec<PathBuf> = paths
+        .into_iter()
+        .map(|path| path.as_ref().to_path_buf())
+        .collect();
+    let git_tracked = options.git_tracked;
+
+    paths.into_iter().flat_map(
+        move |path| -> Box<dyn Iterator<Item = anyhow::Result<PathBuf>>> {
+            #[cfg(feature = "git")]
+            if git_tracked {
+                match crate::git::tracked_files(&path) {
+                    Ok(files) => return Box::new(files.into_iter().map(Ok)),
+                    Err(err) => tracing::debug!("Walking {} instead: {err:#}", path.display()),
+                }
+            }
+            #[cfg(not(feature = "git"))]
+            let _ = git_tracked;
+            Box::new(walk([path]).map(|path| Ok(path?)))
+        },
+    )
+}
+
+// Walks the given paths and counts every file
+pub fn count_paths(
+    paths: impl IntoIterator<Item = impl AsRef<Path>>,
+    options: &CountOptions,
+) -> Report {
+    let mut report = Report::default();
+    for path in files(paths, options) {
+        match path {
+            Ok(path) => {
+                let result = file_info_with_diagnostics(&path, options, &mut report.diagnostics);
+                report.push(path, result);
+            }
+            Err(err) => report.errors.push(err),
+        }
+    }
+    report
+}
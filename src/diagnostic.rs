@@ -0,0 +1,60 @@
+// Per-file findings that do not stop a file from being counted, or explain why it was not
+
+use std::path::{Path, PathBuf};
+
+use crate::language::Language;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum DiagnosticKind {
+    // The file could not be read, it is not counted
+    Unreadable,
+    // Not valid UTF-8, the lines from the first invalid one on are not counted
+    DecodeError,
+    // Counted as `Language::Generic`
+    UnidentifiedLanguage,
+    // A binary format, only counted as a file
+    SkippedBinary,
+    // A block comment still open at the end of the file, most likely a misplaced opening. The
+    // rest of the file was counted as comments, unless recounted.
+    UnterminatedBlockComment,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub kind: DiagnosticKind,
+    // The language the file was counted as, when it got that far
+    pub language: Option<Language>,
+    pub detail: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(path: &Path, kind: DiagnosticKind) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            kind,
+            language: None,
+            detail: None,
+        }
+    }
+
+    pub fn with_language(self, language: Language) -> Self {
+        Self {
+            language: Some(language),
+            ..self
+        }
+    }
+
+    pub fn with_detail(self, detail: impl Into<String>) -> Self {
+        Self {
+            detail: Some(detail.into()),
+            ..self
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+
+use loc::{git::LineChanges, CountOptions, Language};
+
+use crate::{
+    errors::ErrorCollector,
+    sort::{SortArgs, SortKey, Sortable},
+    table::{Table, TableDescriptor, TableDescriptorBuilder, TableFormat, TableWrapper},
+    TableKey,
+};
+
+#[derive(clap::Args)]
+pub struct DiffArgs {
+    /// Revisions to compare, as `A..B`, or `A...B` for the changes on `B` since it forked from
+    /// `A`. `A` alone is `A..HEAD`
+    range: String,
+    #[arg(default_value = ".")]
+    path: PathBuf,
+    #[command(flatten)]
+    sort: SortArgs,
+}
+
+// Sorted by the lines added and removed
+impl Sortable for (Language, LineChanges) {
+    fn name(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn language(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn number(&self, key: SortKey) -> Option<f64> {
+        let (added, removed) = (&self.1.added, &self.1.removed);
+        let lines = match key {
+            SortKey::Code => added.code + removed.code,
+            SortKey::Comments => added.comments + removed.comments,
+            SortKey::Empty => added.empty + removed.empty,
+            SortKey::Total => added.total + removed.total,
+            SortKey::Files => self.1.files,
+            _ => return None,
+        };
+        Some(lines as f64)
+    }
+}
+
+struct TableDiff;
+impl Table for TableDiff {
+    type Key = TableKey;
+    type Value = LineChanges;
+    fn describe() -> TableDescriptor<Self::Value, Self::Key> {
+        TableDescriptorBuilder::column_key("Language", |x: &TableKey| x)
+            .column_with_format("Files", TableFormat::Right, |x: &LineChanges| &x.files)
+            .column("+Code", |x: &LineChanges| &x.added.code)
+            .column("-Code", |x: &LineChanges| &x.removed.code)
+            .column("+Comments", |x: &LineChanges| &x.added.comments)
+            .column("-Comments", |x: &LineChanges| &x.removed.comments)
+            .column("+Empty", |x: &LineChanges| &x.added.empty)
+            .column("-Empty", |x: &LineChanges| &x.removed.empty)
+            .build()
+    }
+}
+
+pub fn run(
+    args: DiffArgs,
+    options: &CountOptions,
+    errors: &mut ErrorCollector,
+) -> anyhow::Result<()> {
+    let diff = loc::git::diff(&args.path, &args.range, options)?;
+    errors.collect(&diff.errors)?;
+    let changes = diff.changes;
+
+    let mut total = LineChanges::default();
+    for language_changes in changes.values() {
+        total.merge_with(language_changes);
+    }
+
+    let mut rows: Vec<_> = changes.into_iter().collect();
+    args.sort.sort(&mut rows)?;
+
+    let rows_iter = rows
+        .into_iter()
+        .map(|(language, changes)| (TableKey::Language(language), changes))
+        .chain(std::iter::once((TableKey::Total, total)));
+    println!("{}", TableWrapper::new::<TableDiff>(rows_iter));
+    errors.finish()
+}
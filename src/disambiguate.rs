@@ -0,0 +1,283 @@
+use crate::language::Language;
+
+// Some extensions are shared between several languages. For those, we take a look at the start of
+// the file and try to guess which one it is, linguist style. Every rule has a fallback so that
+// the outcome is never worse than the old "extension only" behaviour.
+pub fn disambiguate(extension: &str, content: &[u8]) -> Option<Language> {
+    let language = match extension {
+        "h" => {
+            if is_objective_c(content) {
+                Language::ObjectiveC
+            } else if is_cpp(content) {
+                Language::Cpp
+            } else {
+                Language::C
+            }
+        }
+        "m" => {
+            if is_objective_c(content) {
+                Language::ObjectiveC
+            } else if is_matlab(content) {
+                Language::Matlab
+            } else {
+                Language::ObjectiveC
+            }
+        }
+        "pl" => {
+            if is_prolog(content) && !is_perl(content) {
+                Language::Prolog
+            } else {
+                Language::Perl
+            }
+        }
+        "ts" => {
+            if is_mpeg_transport_stream(content) {
+                Language::Asset
+            } else {
+                Language::Typescript
+            }
+        }
+        _ => return None,
+    };
+    Some(language)
+}
+
+fn lines(content: &[u8]) -> impl Iterator<Item = &str> {
+    content
+        .split(|&b| b == b'\n')
+        .filter_map(|line| std::str::from_utf8(line).ok())
+        .map(str::trim)
+}
+
+fn is_objective_c(content: &[u8]) -> bool {
+    lines(content).any(|line| {
+        line.starts_with("#import")
+            || line.starts_with("@interface")
+            || line.starts_with("@implementation")
+            || line.starts_with("@protocol")
+            || line.starts_with("@property")
+            || line.starts_with("@end")
+    })
+}
+
+// C-like code with the comments and the string and character literals blanked out, so that the
+// words in them are not taken for keywords. Lines are kept where they were.
+fn strip_comments_and_literals(text: &str) -> String {
+    let mut code = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'/') => while chars.next_if(|&c| c != '\n').is_some() {},
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        code.push('\n');
+                    }
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+                code.push(' ');
+            }
+            '"' | '\'' => {
+                let mut escaped = false;
+                for next in chars.by_ref() {
+                    // Not closed on its line, most likely not a literal after all
+                    if next == '\n' {
+                        code.push('\n');
+                        break;
+                    }
+                    if !escaped && next == c {
+                        break;
+                    }
+                    escaped = !escaped && next == '\\';
+                }
+                code.push(' ');
+            }
+            c => code.push(c),
+        }
+    }
+    code
+}
+
+// Identifiers and numbers as a whole, anything else one character at a time
+fn tokens(code: &str) -> Vec<&str> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut tokens = vec![];
+    let mut rest = code.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let length = match is_word(c) {
+            true => rest.find(|c| !is_word(c)).unwrap_or(rest.len()),
+            false => c.len_utf8(),
+        };
+        tokens.push(&rest[..length]);
+        rest = rest[length..].trim_start();
+    }
+    tokens
+}
+
+fn is_cpp(content: &[u8]) -> bool {
+    let code = strip_comments_and_literals(&String::from_utf8_lossy(content));
+    // C++ standard headers have no extension: #include <vector>
+    let cpp_include = code.lines().map(str::trim).any(|line| {
+        line.strip_prefix("#include <")
+            .and_then(|header| header.strip_suffix('>'))
+            .is_some_and(|header| !header.contains('.'))
+    });
+    // Whole tokens only, `struct class *cls;` is fine C
+    let is_identifier = |token: &str| token.starts_with(|c: char| c.is_alphabetic() || c == '_');
+    cpp_include
+        || tokens(&code).windows(3).any(|window| match window {
+            ["class", name, "{" | ":"] => is_identifier(name),
+            ["namespace", name, _] => is_identifier(name) || *name == "{",
+            ["template", "<", _] | ["std", ":", ":"] | ["using", "namespace", _] => true,
+            _ => false,
+        })
+}
+
+fn is_matlab(content: &[u8]) -> bool {
+    lines(content).any(|line| {
+        line.starts_with('%') || line.starts_with("function ") || line.starts_with("classdef ")
+    })
+}
+
+fn is_perl(content: &[u8]) -> bool {
+    lines(content).any(|line| {
+        (line.starts_with("#!") && line.contains("perl"))
+            || line.starts_with("use strict")
+            || line.starts_with("use warnings")
+            || line.starts_with("my ")
+            || line.starts_with("sub ")
+            || line.starts_with("package ")
+    })
+}
+
+// A directive or a rule, `%` comments look too much like Perl hashes
+fn is_prolog(content: &[u8]) -> bool {
+    lines(content)
+        .any(|line| line.starts_with(":-") || (line.contains(":-") && line.ends_with('.')))
+}
+
+// MPEG transport streams are made of 188 bytes packets, each starting with the 0x47 sync byte.
+// A few full packets are needed, a lone 0x47 is just a source file starting with `G`.
+fn is_mpeg_transport_stream(content: &[u8]) -> bool {
+    const PACKET_LENGTH: usize = 188;
+    const MIN_PACKETS: usize = 3;
+    const SYNC_BYTE: u8 = 0x47;
+
+    content.len() >= MIN_PACKETS * PACKET_LENGTH
+        && content
+            .iter()
+            .step_by(PACKET_LENGTH)
+            .all(|&byte| byte == SYNC_BYTE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers() {
+        assert_eq!(disambiguate("h", b"int f(void);\n"), Some(Language::C));
+        assert_eq!(
+            disambiguate("h", b"#include <vector>\nint f();\n"),
+            Some(Language::Cpp)
+        );
+        assert_eq!(
+            disambiguate("h", b"// class in a comment\nint f(void);\n"),
+            Some(Language::C)
+        );
+        for c in [
+            "struct my_class *c;\n",
+            "struct class *cls;\n",
+            "#define MSG \"now using cache\"\n",
+            "/*\n This class of functions { is C\n */\nint f(void);\n",
+            "int namespace;\nint template = 1 < 2;\n",
+        ] {
+            assert_eq!(disambiguate("h", c.as_bytes()), Some(Language::C), "{c}");
+        }
+        for cpp in [
+            "class A {\n};\n",
+            "class B : public A {};\n",
+            "namespace a {\n}\n",
+            "template <typename T>\nT f(T);\n",
+            "std::string f();\n",
+            "using namespace std;\n",
+        ] {
+            assert_eq!(
+                disambiguate("h", cpp.as_bytes()),
+                Some(Language::Cpp),
+                "{cpp}"
+            );
+        }
+        assert_eq!(
+            disambiguate(
+                "h",
+                b"#import <Foundation/Foundation.h>\n@interface A\n@end\n"
+            ),
+            Some(Language::ObjectiveC)
+        );
+    }
+
+    #[test]
+    fn m_files() {
+        assert_eq!(
+            disambiguate("m", b"function y = f(x)\n  y = x;\nend\n"),
+            Some(Language::Matlab)
+        );
+        assert_eq!(
+            disambiguate("m", b"@implementation A\n@end\n"),
+            Some(Language::ObjectiveC)
+        );
+        assert_eq!(disambiguate("m", b""), Some(Language::ObjectiveC));
+    }
+
+    #[test]
+    fn pl_files() {
+        assert_eq!(
+            disambiguate("pl", b":- module(a, []).\nparent(a, b).\n"),
+            Some(Language::Prolog)
+        );
+        assert_eq!(
+            disambiguate("pl", b"#!/usr/bin/perl\nuse strict;\nmy $x = 1;\n"),
+            Some(Language::Perl)
+        );
+        assert_eq!(disambiguate("pl", b"print 1;\n"), Some(Language::Perl));
+        assert_eq!(
+            disambiguate("pl", b"%config = (a => 1);\nprint $config{a};\n"),
+            Some(Language::Perl)
+        );
+        assert_eq!(
+            disambiguate(
+                "pl",
+                b"% family\nparent(a, b).\nanc(X, Y) :- parent(X, Y).\n"
+            ),
+            Some(Language::Prolog)
+        );
+    }
+
+    #[test]
+    fn ts_files() {
+        assert_eq!(
+            disambiguate("ts", b"Goo = 1;\nexport {};\n"),
+            Some(Language::Typescript)
+        );
+        assert_eq!(disambiguate("ts", b"G"), Some(Language::Typescript));
+
+        let mut stream = vec![0; 188 * 4];
+        for packet in stream.chunks_mut(188) {
+            packet[0] = 0x47;
+        }
+        assert_eq!(disambiguate("ts", &stream), Some(Language::Asset));
+        stream[188] = 0;
+        assert_eq!(disambiguate("ts", &stream), Some(Language::Typescript));
+    }
+
+    #[test]
+    fn unambiguous_extensions() {
+        assert_eq!(disambiguate("rs", b"fn main() {}\n"), None);
+    }
+}
@@ -0,0 +1,99 @@
+use std::{fmt::Display, process::ExitCode};
+
+pub const EXIT_STATUS_HELP: &str = "\
+Exit status:
+  0  everything was counted
+  1  some files could not be counted, the others were
+  2  invalid arguments, or a policy, ratchet or baseline file that cannot be read
+  3  a check found policy violations
+  4  nothing could be counted, e.g. not a repository or an unknown revision";
+
+// Arguments that make no sense together, or input files given as arguments that cannot be used,
+// reported with the exit status of clap's own errors
+#[derive(Debug)]
+pub struct UsageError(pub String);
+
+impl Display for UsageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+// Some files could not be counted, what could be was reported
+#[derive(Debug)]
+pub struct PartialFailure(pub usize);
+
+impl Display for PartialFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            1 => write!(f, "1 file could not be counted"),
+            count => write!(f, "{count} files could not be counted"),
+        }
+    }
+}
+
+impl std::error::Error for PartialFailure {}
+
+// Everything was counted, but `check` did not like it
+#[derive(Debug)]
+pub struct PolicyViolation(pub usize);
+
+impl Display for PolicyViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} policy violation(s)", self.0)
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+// Whether `err` is a `T`, has one as its context, or has one somewhere under its context
+fn is<T: std::error::Error + Send + Sync + 'static>(err: &anyhow::Error) -> bool {
+    err.is::<T>() || err.chain().any(|cause| cause.is::<T>())
+}
+
+pub fn exit_code(err: &anyhow::Error) -> ExitCode {
+    if is::<UsageError>(err) {
+        ExitCode::from(2)
+    } else if is::<PartialFailure>(err) {
+        ExitCode::from(1)
+    } else if is::<PolicyViolation>(err) {
+        ExitCode::from(3)
+    } else {
+        ExitCode::from(4)
+    }
+}
+
+// Reports the files that could not be counted on stderr, as they come, and how many there were
+// at the end
+#[derive(Debug, Default)]
+pub struct ErrorCollector {
+    count: usize,
+    // Fail on the first batch of errors, before anything incomplete gets printed
+    strict: bool,
+}
+
+impl ErrorCollector {
+    pub fn new(strict: bool) -> Self {
+        Self { count: 0, strict }
+    }
+
+    pub fn collect(&mut self, errors: &[anyhow::Error]) -> anyhow::Result<()> {
+        for err in errors {
+            eprintln!("error: {err:#}");
+        }
+        self.count += errors.len();
+        if self.strict {
+            self.finish()?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(&self) -> anyhow::Result<()> {
+        match self.count {
+            0 => Ok(()),
+            count => anyhow::bail!(PartialFailure(count)),
+        }
+    }
+}
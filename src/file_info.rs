@@ -1,10 +1,13 @@
 use crate::{
-    identify::identify,
-    language::{make_line_kind_estimator, Language},
+    count::CountOptions,
+    diagnostic::{Diagnostic, DiagnosticKind},
+    identify::{identify_content, or_generic, try_identify},
+    language::{make_line_comment_estimator, make_line_kind_estimator, Language},
     line_kind::{LineKind, LineKindEstimator},
 };
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FileInfo {
     pub textual: bool,
     pub total: usize,
@@ -51,57 +54,322 @@ impl FileInfo {
     }
 }
 
-pub async fn file_info_from_path(
+pub fn file_info_from_path(
     file: &std::path::Path,
     debug: bool,
 ) -> std::io::Result<(FileInfo, Language)> {
-    let language = identify(file, debug);
-    let file_infos = gen_file_info(file, make_line_kind_estimator(language)).await?;
+    let options = CountOptions {
+        debug,
+        ..Default::default()
+    };
+    file_info_with_diagnostics(file, &options, &mut vec![])
+}
+
+// Same as `file_info_from_path`, noting what did not go as planned on the way
+pub(crate) fn file_info_with_diagnostics(
+    file: &std::path::Path,
+    options: &CountOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> std::io::Result<(FileInfo, Language)> {
+    let language = identify_for_count(file, options.debug, diagnostics);
+    let file_infos = match LineCounter::for_file(file, language, options, diagnostics) {
+        Some(counter) => {
+            let f = std::fs::File::open(file)?;
+            count_lines(std::io::BufReader::new(f), counter)?.finish_file(
+                file,
+                language,
+                diagnostics,
+            )
+        }
+        None => FileInfo::new_non_text(),
+    };
 
     tracing::trace!(?file, ?language, ?file_infos, "Generated file info");
     Ok((file_infos, language))
 }
 
-async fn read_line<'a, T: tokio::io::AsyncRead + std::marker::Unpin>(
-    buf: &mut tokio::io::BufReader<T>,
-    s: &'a mut String,
-) -> Option<&'a str> {
-    s.clear();
+// The language to count a file on disk as, noting when nothing pointed to one
+pub(crate) fn identify_for_count(
+    file: &std::path::Path,
+    debug: bool,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Language {
+    let identified = try_identify(file);
+    if identified.is_none() {
+        diagnostics.push(Diagnostic::new(file, DiagnosticKind::UnidentifiedLanguage));
+    }
+    or_generic(file, identified, debug)
+}
+
+// How to pick the language of content that does not come from a file on disk
+#[derive(Debug, Clone, Copy)]
+pub enum LanguageHint<'a> {
+    Language(Language),
+    // Identified as if it was the content of a file with this name
+    Filename(&'a std::path::Path),
+}
+
+impl From<Language> for LanguageHint<'_> {
+    fn from(language: Language) -> Self {
+        LanguageHint::Language(language)
+    }
+}
+
+impl<'a> From<&'a std::path::Path> for LanguageHint<'a> {
+    fn from(path: &'a std::path::Path) -> Self {
+        LanguageHint::Filename(path)
+    }
+}
+
+impl<'a> From<&'a str> for LanguageHint<'a> {
+    fn from(filename: &'a str) -> Self {
+        LanguageHint::Filename(std::path::Path::new(filename))
+    }
+}
+
+pub fn file_info_from_bytes<'a>(
+    content: &[u8],
+    hint: impl Into<LanguageHint<'a>>,
+) -> (FileInfo, Language) {
+    let language = match hint.into() {
+        LanguageHint::Language(language) => language,
+        LanguageHint::Filename(path) => identify_content(path, content, false),
+    };
+    let file_infos = gen_file_info_from_bytes(content, make_line_kind_estimator(language));
+    (file_infos, language)
+}
+
+pub fn file_info_from_str<'a>(
+    content: &str,
+    hint: impl Into<LanguageHint<'a>>,
+) -> (FileInfo, Language) {
+    file_info_from_bytes(content.as_bytes(), hint)
+}
 
-    use tokio::io::AsyncBufReadExt;
-    match buf.read_line(s).await {
-        Err(_) => None,
-        Ok(0) => None,
-        Ok(_n) => Some(s),
+pub fn file_info_from_reader<'a>(
+    mut reader: impl std::io::Read,
+    hint: impl Into<LanguageHint<'a>>,
+) -> std::io::Result<(FileInfo, Language)> {
+    match hint.into() {
+        LanguageHint::Language(language) => {
+            let file_infos = gen_file_info_from_reader(
+                std::io::BufReader::new(reader),
+                make_line_kind_estimator(language),
+            )?;
+            Ok((file_infos, language))
+        }
+        // Identification may look at the end of the content
+        hint @ LanguageHint::Filename(_) => {
+            let mut content = Vec::new();
+            reader.read_to_end(&mut content)?;
+            Ok(file_info_from_bytes(&content, hint))
+        }
     }
 }
 
-pub async fn gen_file_info(
+// Feeds lines to an estimator and tallies what it says about them
+pub(crate) struct LineCounter {
+    file_info: FileInfo,
+    line_kind_estimator: Box<dyn LineKindEstimator + Send>,
+    // The first line that is not valid UTF-8, from 1
+    invalid_line: Option<usize>,
+    // Where the block comment the estimator is in was opened
+    block_comment_line: Option<usize>,
+    // The same lines counted as if the language had no block comments, in case one is never closed
+    recount: Option<(FileInfo, Box<dyn LineKindEstimator + Send>)>,
+}
+
+fn tally(file_info: &mut FileInfo, kind: LineKind) {
+    file_info.total += 1;
+    match kind {
+        LineKind::Comment => file_info.comments += 1,
+        LineKind::Code => file_info.code += 1,
+        LineKind::Empty => file_info.empty += 1,
+    }
+}
+
+impl LineCounter {
+    pub(crate) fn new(line_kind_estimator: Box<dyn LineKindEstimator + Send>) -> Self {
+        Self {
+            file_info: FileInfo::new(),
+            line_kind_estimator,
+            invalid_line: None,
+            block_comment_line: None,
+            recount: None,
+        }
+    }
+
+    // Counts a file of `language` the way `count_paths` does, None for the binary formats. Both
+    // the files on disk and the blobs of a repository go through here.
+    pub(crate) fn for_file(
+        file: &std::path::Path,
+        language: Language,
+        options: &CountOptions,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<Self> {
+        let Some(line_kind_estimator) = make_line_kind_estimator(language) else {
+            diagnostics
+                .push(Diagnostic::new(file, DiagnosticKind::SkippedBinary).with_language(language));
+            return None;
+        };
+        let mut counter = Self::new(line_kind_estimator);
+        if options.recount_unterminated {
+            counter.recount = Some((FileInfo::new(), make_line_comment_estimator(language)));
+        }
+        Some(counter)
+    }
+
+    // Returns false when the line is not valid UTF-8, the rest of the file should then be ignored
+    pub(crate) fn count(&mut self, line: &[u8]) -> bool {
+        let Ok(line) = std::str::from_utf8(line) else {
+            self.invalid_line = Some(self.file_info.total + 1);
+            return false;
+        };
+
+        tally(&mut self.file_info, self.line_kind_estimator.estimate(line));
+        if let Some((recounted, estimator)) = &mut self.recount {
+            tally(recounted, estimator.estimate(line));
+        }
+        if !self.line_kind_estimator.in_block_comment() {
+            self.block_comment_line = None;
+        } else if self.block_comment_line.is_none() {
+            self.block_comment_line = Some(self.file_info.total);
+        }
+        true
+    }
+
+    // What went wrong while counting, if anything
+    fn diagnostics(
+        &self,
+        file: &std::path::Path,
+        language: Language,
+    ) -> impl Iterator<Item = Diagnostic> {
+        let decode_error = self.invalid_line.map(|line| {
+            Diagnostic::new(file, DiagnosticKind::DecodeError)
+                .with_language(language)
+                .with_detail(format!("invalid UTF-8 on line {line}"))
+        });
+        // A file cut short by a decode error may well close its comment later
+        let unterminated = self
+            .block_comment_line
+            .filter(|_| self.invalid_line.is_none())
+            .map(|line| {
+                Diagnostic::new(file, DiagnosticKind::UnterminatedBlockComment)
+                    .with_language(language)
+                    .with_detail(format!(
+                        "block comment opened on line {line} is never closed"
+                    ))
+            });
+        decode_error.into_iter().chain(unterminated)
+    }
+
+    pub(crate) fn finish(self) -> FileInfo {
+        self.file_info
+    }
+
+    // The counts of a file started with `for_file`, recounted if asked to and needed, noting
+    // what went wrong in `diagnostics`
+    pub(crate) fn finish_file(
+        self,
+        file: &std::path::Path,
+        language: Language,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> FileInfo {
+        diagnostics.extend(self.diagnostics(file, language));
+        match self.recount {
+            Some((recounted, _)) if self.block_comment_line.is_some() => recounted,
+            _ => self.file_info,
+        }
+    }
+}
+
+pub(crate) fn count_lines(
+    mut reader: impl std::io::BufRead,
+    mut counter: LineCounter,
+) -> std::io::Result<LineCounter> {
+    let mut line_buf = Vec::new();
+    while reader.read_until(b'\n', &mut line_buf)? != 0 {
+        if !counter.count(&line_buf) {
+            break;
+        }
+        line_buf.clear();
+    }
+    Ok(counter)
+}
+
+pub fn gen_file_info_from_reader(
+    reader: impl std::io::BufRead,
+    line_kind_estimator: Option<Box<dyn LineKindEstimator + Send>>,
+) -> std::io::Result<FileInfo> {
+    let Some(line_kind_estimator) = line_kind_estimator else {
+        return Ok(FileInfo::new_non_text());
+    };
+
+    Ok(count_lines(reader, LineCounter::new(line_kind_estimator))?.finish())
+}
+
+pub fn gen_file_info_from_bytes(
+    content: &[u8],
+    line_kind_estimator: Option<Box<dyn LineKindEstimator + Send>>,
+) -> FileInfo {
+    gen_file_info_from_reader(content, line_kind_estimator)
+        .expect("reading from a slice never fails")
+}
+
+pub fn gen_file_info(
     file: &std::path::Path,
     line_kind_estimator: Option<Box<dyn LineKindEstimator + Send>>,
 ) -> std::io::Result<FileInfo> {
-    let mut file_info = FileInfo::new();
-    match line_kind_estimator {
-        Some(mut line_kind_estimator) => {
-            let f = tokio::fs::OpenOptions::new()
-                .read(true)
-                .write(false)
-                .open(file)
-                .await?;
-
-            let mut buffered = tokio::io::BufReader::new(f);
-            let mut line_buf = String::new();
-            while let Some(li) = read_line(&mut buffered, &mut line_buf).await {
-                file_info.total += 1;
-                match line_kind_estimator.estimate(li) {
-                    LineKind::Comment => file_info.comments += 1,
-                    LineKind::Code => file_info.code += 1,
-                    LineKind::Empty => file_info.empty += 1,
-                }
-            }
-
-            Ok(file_info)
-        }
-        None => Ok(FileInfo::new_non_text()),
+    // No need to open the file when its content is not looked at
+    if line_kind_estimator.is_none() {
+        return Ok(FileInfo::new_non_text());
+    }
+
+    let f = std::fs::File::open(file)?;
+    gen_file_info_from_reader(std::io::BufReader::new(f), line_kind_estimator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostics(content: &str) -> Vec<Diagnostic> {
+        let estimator = make_line_kind_estimator(Language::C).unwrap();
+        count_lines(content.as_bytes(), LineCounter::new(estimator))
+            .unwrap()
+            .diagnostics(std::path::Path::new("a.c"), Language::C)
+            .collect()
+    }
+
+    #[test]
+    fn unterminated_block_comments() {
+        assert_eq!(diagnostics("/* header */ int x = 1;\nint y = 2;\n"), []);
+        assert_eq!(diagnostics("/*\n * a\n */\nint x;\n"), []);
+
+        let found = diagnostics("int x;\n/* a\nint y;\n");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].kind, DiagnosticKind::UnterminatedBlockComment);
+        assert_eq!(
+            found[0].detail.as_deref(),
+            Some("block comment opened on line 2 is never closed")
+        );
+    }
+
+    #[test]
+    fn recount_unterminated() {
+        let count = |content: &str, recount_unterminated| {
+            let options = CountOptions {
+                recount_unterminated,
+                ..Default::default()
+            };
+            let path = std::path::Path::new("a.c");
+            let counter = LineCounter::for_file(path, Language::C, &options, &mut vec![]);
+            let counter = count_lines(content.as_bytes(), counter.unwrap()).unwrap();
+            let info = counter.finish_file(path, Language::C, &mut vec![]);
+            (info.code, info.comments)
+        };
+        assert_eq!(count("int x;\n/* a\nint y;\n", false), (1, 2));
+        assert_eq!(count("int x;\n/* a\nint y;\n", true), (3, 0));
+        assert_eq!(count("int x;\n/* a */\nint y;\n", true), (2, 1));
     }
 }
@@ -0,0 +1,698 @@
+// Counting straight from a git object database, without touching the working directory
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::{
+    count::{CountOptions, FileEntry, Report},
+    diagnostic::Diagnostic,
+    file_info::{count_lines, FileInfo, LineCounter},
+    identify::identify_content,
+    language::{make_line_kind_estimator, Language},
+    line_kind::LineKind,
+};
+
+// The same blob under the same file name is always counted the same way, and goes wrong the same
+// way too
+pub type BlobCache = HashMap<(git2::Oid, String), (FileInfo, Language, Vec<Diagnostic>)>;
+
+// The repository containing `path`, and where `path` is inside of it
+pub fn open_repository(path: &Path) -> anyhow::Result<(git2::Repository, PathBuf)> {
+    // The path may only exist at some revision, the rest of it is taken as is
+    let existing = path
+        .ancestors()
+        .find(|ancestor| ancestor.as_os_str().is_empty() || ancestor.exists())
+        .unwrap_or(path);
+    let missing = path.strip_prefix(existing).unwrap_or(Path::new(""));
+    let existing = match existing.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => existing,
+    };
+
+    let repo = git2::Repository::discover(existing)
+        .with_context(|| format!("while looking for a git repository at {}", path.display()))?;
+    let prefix = match repo.workdir() {
+        Some(workdir) => {
+            let workdir = workdir.canonicalize()?;
+            let existing = existing.canonicalize()?;
+            existing
+                .strip_prefix(&workdir)
+                .map(Path::to_path_buf)
+                .unwrap_or_default()
+                .join(missing)
+        }
+        None => PathBuf::new(),
+    };
+    Ok((repo, prefix))
+}
+
+// The tree of `rev`, restricted to `prefix`
+pub fn tree_at<'r>(
+    repo: &'r git2::Repository,
+    rev: &str,
+    prefix: &Path,
+) -> anyhow::Result<git2::Tree<'r>> {
+    let tree = repo
+        .revparse_single(rev)
+        .and_then(|object| object.peel_to_tree())
+        .with_context(|| format!("while resolving {rev}"))?;
+    if prefix.as_os_str().is_empty() {
+        return Ok(tree);
+    }
+
+    let subtree = tree
+        .get_path(prefix)
+        .and_then(|entry| entry.to_object(repo))
+        .and_then(|object| object.peel_to_tree())
+        .with_context(|| format!("while looking for {} in {rev}", prefix.display()))?;
+    Ok(subtree)
+}
+
+pub fn count_blob(
+    repo: &git2::Repository,
+    id: git2::Oid,
+    path: &Path,
+    options: &CountOptions,
+    cache: &mut BlobCache,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> anyhow::Result<(FileInfo, Language)> {
+    let filename = path
+        .file_name()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if let Some((file_info, language, found)) = cache.get(&(id, filename.clone())) {
+        diagnostics.extend(found.iter().map(|diagnostic| Diagnostic {
+            path: path.to_path_buf(),
+            ..diagnostic.clone()
+        }));
+        return Ok((file_info.clone(), *language));
+    }
+
+    let blob = repo.find_blob(id)?;
+    let language = identify_content(path, blob.content(), options.debug);
+    let mut found = vec![];
+    let file_info = match LineCounter::for_file(path, language, options, &mut found) {
+        Some(counter) => {
+            count_lines(blob.content(), counter)?.finish_file(path, language, &mut found)
+        }
+        None => FileInfo::new_non_text(),
+    };
+    diagnostics.extend(found.iter().cloned());
+    cache.insert((id, filename), (file_info.clone(), language, found));
+    Ok((file_info, language))
+}
+
+// Counts every file of `tree`. Paths in the report are `root` joined with the path in the tree.
+pub fn count_tree(
+    repo: &git2::Repository,
+    tree: &git2::Tree,
+    root: &Path,
+    options: &CountOptions,
+    cache: &mut BlobCache,
+) -> Report {
+    let mut report = Report::default();
+    let walked = tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+        // Hidden files are skipped, like when walking the disk
+        if entry.name_bytes().starts_with(b".") {
+            return git2::TreeWalkResult::Skip;
+        }
+        // Symlinks are blobs too, and submodules are commits
+        if entry.kind() != Some(git2::ObjectType::Blob) || entry.filemode() == 0o120000 {
+            return git2::TreeWalkResult::Ok;
+        }
+
+        let path = Path::new(dir).join(String::from_utf8_lossy(entry.name_bytes()).as_ref());
+        let mut diagnostics = vec![];
+        let counted = count_blob(repo, entry.id(), &path, options, cache, &mut diagnostics)
+            .with_context(|| format!("while getting file infos from {}", path.display()));
+        match counted {
+            Ok((info, language)) => report.files.push(FileEntry {
+                path: root.join(path),
+                language,
+                info,
+            }),
+            Err(err) => report.errors.push(err),
+        }
+        report
+            .diagnostics
+            .extend(diagnostics.into_iter().map(|diagnostic| Diagnostic {
+                path: root.join(&diagnostic.path),
+                ..diagnostic
+            }));
+        git2::TreeWalkResult::Ok
+    });
+    if let Err(err) = walked {
+        report.errors.push(err.into());
+    }
+    report
+}
+
+// Counts the files under `path` as they were at `rev`
+pub fn count_rev(path: &Path, rev: &str, options: &CountOptions) -> anyhow::Result<Report> {
+    let (repo, prefix) = open_repository(path)?;
+    let tree = tree_at(&repo, rev, &prefix)?;
+    Ok(count_tree(
+        &repo,
+        &tree,
+        path,
+        options,
+        &mut BlobCache::new(),
+    ))
+}
+
+// The files under `path` recorded in the index, as paths below `path`. Hidden files are skipped
+// like when walking the disk, and so are tracked files missing from the working directory.
+pub fn tracked_files(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let (repo, prefix) = open_repository(path)?;
+    let workdir = repo
+        .workdir()
+        .with_context(|| format!("{} is in a bare repository", path.display()))?;
+    let index = repo.index().context("while reading the git index")?;
+
+    let mut files = Vec::new();
+    for entry in index.iter() {
+        // Only the merged stage of conflicted files, and neither symlinks nor submodules
+        let stage = (entry.flags >> 12) & 0b11;
+        if stage != 0 || entry.mode & 0o170000 != 0o100000 {
+            continue;
+        }
+        let tracked = PathBuf::from(String::from_utf8_lossy(&entry.path).as_ref());
+        let Ok(relative) = tracked.strip_prefix(&prefix) else {
+            continue;
+        };
+        if relative
+            .components()
+            .any(|component| component.as_os_str().as_encoded_bytes().starts_with(b"."))
+        {
+            continue;
+        }
+        if !workdir.join(&tracked).is_file() {
+            continue;
+        }
+        files.push(below(path, relative));
+    }
+    Ok(files)
+}
+
+// `relative` as found under `path`, which may be the file itself
+fn below(path: &Path, relative: &Path) -> PathBuf {
+    if relative.as_os_str().is_empty() {
+        path.to_path_buf()
+    } else {
+        path.join(relative)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sampling {
+    // Every N commits, starting from the most recent one
+    Commits(usize),
+    // The last commit of every day, or week, UTC
+    Day,
+    Week,
+    // Every tagged commit
+    Tag,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HistoryPoint {
+    pub commit: String,
+    // Commit time, in seconds since the epoch
+    pub time: i64,
+    pub tag: Option<String>,
+    pub total: FileInfo,
+    pub languages: std::collections::BTreeMap<Language, FileInfo>,
+}
+
+#[derive(Debug, Default)]
+pub struct History {
+    pub points: Vec<HistoryPoint>,
+    // Files that could not be counted at some commit, they are not part of its point
+    pub errors: Vec<anyhow::Error>,
+}
+
+// The commits to count, oldest first
+fn sample_commits<'r>(
+    repo: &'r git2::Repository,
+    rev: &str,
+    sampling: Sampling,
+) -> anyhow::Result<Vec<(git2::Commit<'r>, Option<String>)>> {
+    if sampling == Sampling::Tag {
+        let mut tagged = vec![];
+        for name in repo.tag_names(None)?.iter().flatten() {
+            let commit = repo
+                .revparse_single(&format!("refs/tags/{name}"))
+                .and_then(|object| object.peel_to_commit())
+                .with_context(|| format!("while resolving tag {name}"))?;
+            tagged.push((commit, Some(name.to_string())));
+        }
+        tagged.sort_by_key(|(commit, _)| commit.time().seconds());
+        return Ok(tagged);
+    }
+
+    let head = repo
+        .revparse_single(rev)
+        .and_then(|object| object.peel_to_commit())
+        .with_context(|| format!("while resolving {rev}"))?;
+    let mut walk = repo.revwalk()?;
+    walk.push(head.id())?;
+    // Merged branches would make the history go back and forth in time
+    walk.simplify_first_parent()?;
+
+    const DAY: i64 = 24 * 60 * 60;
+    let mut sampled: Vec<git2::Commit> = vec![];
+    for (i, id) in walk.enumerate() {
+        let commit = repo.find_commit(id?)?;
+        let keep = match sampling {
+            Sampling::Commits(n) => i % n.max(1) == 0,
+            // The walk goes back in time, the first commit seen in a period is its last one
+            Sampling::Day | Sampling::Week => {
+                // The epoch was a thursday, shift so that weeks start on monday
+                let period = |commit: &git2::Commit| match sampling {
+                    Sampling::Week => (commit.time().seconds().div_euclid(DAY) + 3).div_euclid(7),
+                    _ => commit.time().seconds().div_euclid(DAY),
+                };
+                sampled
+                    .last()
+                    .is_none_or(|last| period(last) != period(&commit))
+            }
+            Sampling::Tag => unreachable!(),
+        };
+        if keep {
+            sampled.push(commit);
+        }
+    }
+
+    sampled.reverse();
+    Ok(sampled.into_iter().map(|commit| (commit, None)).collect())
+}
+
+// Counts the files under `path` at every sampled commit of the history of `rev`
+pub fn history(
+    path: &Path,
+    rev: &str,
+    sampling: Sampling,
+    options: &CountOptions,
+) -> anyhow::Result<History> {
+    let (repo, prefix) = open_repository(path)?;
+    let mut cache = BlobCache::new();
+
+    let mut history = History::default();
+    for (commit, tag) in sample_commits(&repo, rev, sampling)? {
+        let commit_id = commit.id().to_string();
+        // The path may not exist yet this far back
+        let tree = match tree_at(&repo, &commit_id, &prefix) {
+            Ok(tree) => tree,
+            Err(err) => {
+                tracing::debug!("skipping {commit_id}: {err:#}");
+                continue;
+            }
+        };
+
+        let report = count_tree(&repo, &tree, path, options, &mut cache);
+        history.points.push(HistoryPoint {
+            commit: commit_id.clone(),
+            time: commit.time().seconds(),
+            tag,
+            total: report.total(),
+            languages: report.by_language().into_iter().collect(),
+        });
+        history.errors.extend(
+            report
+                .errors
+                .into_iter()
+                .map(|err| err.context(format!("in {commit_id}"))),
+        );
+    }
+    Ok(history)
+}
+
+// What a diff did to the lines of some files
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LineChanges {
+    // Number of files changed
+    pub files: usize,
+    pub added: FileInfo,
+    pub removed: FileInfo,
+}
+
+impl LineChanges {
+    pub fn merge_with(&mut self, other: &Self) {
+        self.files += other.files;
+        self.added.merge_with(&other.added);
+        self.removed.merge_with(&other.removed);
+    }
+}
+
+// The kind of every line of a blob. Lines are estimated in order, so that a line added in the
+// middle of a block comment is seen as a comment.
+fn line_kinds(content: &[u8], language: Language) -> Option<Vec<LineKind>> {
+    let mut estimator = make_line_kind_estimator(language)?;
+    let kinds = content
+        .split_inclusive(|&b| b == b'\n')
+        .map_while(|line| std::str::from_utf8(line).ok())
+        .map(|line| estimator.estimate(line))
+        .collect();
+    Some(kinds)
+}
+
+fn count_line(file_info: &mut FileInfo, kind: Option<&LineKind>) {
+    // Lines past an invalid UTF-8 one are not counted, as when counting whole files
+    let Some(kind) = kind else {
+        return;
+    };
+    file_info.textual = true;
+    file_info.total += 1;
+    match kind {
+        LineKind::Comment => file_info.comments += 1,
+        LineKind::Code => file_info.code += 1,
+        LineKind::Empty => file_info.empty += 1,
+    }
+}
+
+// Whether this side of a delta is a file to count, like in `count_tree`: not missing, not a
+// symlink and not a submodule
+fn is_counted_file(file: &git2::DiffFile) -> bool {
+    matches!(
+        file.mode(),
+        git2::FileMode::Blob | git2::FileMode::BlobExecutable
+    )
+}
+
+fn blob_line_kinds(
+    repo: &git2::Repository,
+    file: git2::DiffFile,
+    language: Language,
+) -> anyhow::Result<Option<Vec<LineKind>>> {
+    if !is_counted_file(&file) {
+        return Ok(Some(vec![]));
+    }
+    let blob = repo.find_blob(file.id())?;
+    Ok(line_kinds(blob.content(), language))
+}
+
+// The lines added and removed in a single file, and its language
+fn diff_file(
+    repo: &git2::Repository,
+    patch: &git2::Patch,
+    file_path: &Path,
+    options: &CountOptions,
+) -> anyhow::Result<(Language, LineChanges)> {
+    let delta = patch.delta();
+    // The language is the one of the file after the change, or before if it was removed
+    let language = {
+        let file = if is_counted_file(&delta.new_file()) {
+            delta.new_file()
+        } else {
+            delta.old_file()
+        };
+        let blob = repo.find_blob(file.id())?;
+        identify_content(file_path, blob.content(), options.debug)
+    };
+    let old_kinds = blob_line_kinds(repo, delta.old_file(), language)?;
+    let new_kinds = blob_line_kinds(repo, delta.new_file(), language)?;
+
+    let mut file_changes = LineChanges {
+        files: 1,
+        ..Default::default()
+    };
+    if let (Some(old_kinds), Some(new_kinds)) = (old_kinds, new_kinds) {
+        for hunk in 0..patch.num_hunks() {
+            for line in 0..patch.num_lines_in_hunk(hunk)? {
+                let line = patch.line_in_hunk(hunk, line)?;
+                match (line.origin(), line.old_lineno(), line.new_lineno()) {
+                    ('+', _, Some(n)) => {
+                        count_line(&mut file_changes.added, new_kinds.get(n as usize - 1))
+                    }
+                    ('-', Some(n), _) => {
+                        count_line(&mut file_changes.removed, old_kinds.get(n as usize - 1))
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+    Ok((language, file_changes))
+}
+
+// `A..B`, `A...B`, or `A` for `A..HEAD`. True for the three dots, where the changes are the ones
+// made on `B` since it forked from `A`, like `git diff`.
+fn split_range(range: &str) -> (&str, &str, bool) {
+    fn or_head(rev: &str) -> &str {
+        if rev.is_empty() {
+            "HEAD"
+        } else {
+            rev
+        }
+    }
+
+    if let Some((from, to)) = range.split_once("...") {
+        return (or_head(from), or_head(to), true);
+    }
+    match range.split_once("..") {
+        Some((from, to)) => (or_head(from), or_head(to), false),
+        None => (range, "HEAD", false),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Diff {
+    pub changes: std::collections::BTreeMap<Language, LineChanges>,
+    // Files that could not be diffed, they are not part of `changes`
+    pub errors: Vec<anyhow::Error>,
+}
+
+// Classifies the lines added and removed between the two revisions of `range`, per language
+pub fn diff(path: &Path, range: &str, options: &CountOptions) -> anyhow::Result<Diff> {
+    let (repo, prefix) = open_repository(path)?;
+    let (from, to, since_fork) = split_range(range);
+    let from = match since_fork {
+        true => {
+            let commit = |rev: &str| {
+                repo.revparse_single(rev)
+                    .and_then(|object| object.peel_to_commit())
+                    .with_context(|| format!("while resolving {rev}"))
+            };
+            let base = repo
+                .merge_base(commit(from)?.id(), commit(to)?.id())
+                .with_context(|| format!("while looking for the merge base of {range}"))?;
+            base.to_string()
+        }
+        false => from.to_string(),
+    };
+    let old_tree = tree_at(&repo, &from, &prefix)?;
+    let new_tree = tree_at(&repo, to, &prefix)?;
+
+    let mut diff = repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None)?;
+    diff.find_similar(None)?;
+
+    let mut result = Diff::default();
+    for i in 0..diff.deltas().len() {
+        let Some(patch) = git2::Patch::from_diff(&diff, i)? else {
+            continue;
+        };
+        let delta = patch.delta();
+        let Some(file_path) = delta.new_file().path().or(delta.old_file().path()) else {
+            continue;
+        };
+        if file_path
+            .components()
+            .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+        {
+            continue;
+        }
+        if !is_counted_file(&delta.new_file()) && !is_counted_file(&delta.old_file()) {
+            continue;
+        }
+
+        match diff_file(&repo, &patch, file_path, options)
+            .with_context(|| format!("while diffing {}", file_path.display()))
+        {
+            Ok((language, file_changes)) => result
+                .changes
+                .entry(language)
+                .or_default()
+                .merge_with(&file_changes),
+            Err(err) => result.errors.push(err),
+        }
+    }
+
+    Ok(result)
+}
+
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Authorship {
+    // Lines last changed by an author, per author and language. The file count is the number of
+    // files the author has lines in.
+    pub by_author: std::collections::BTreeMap<(String, Language), FileInfo>,
+    pub total: FileInfo,
+    // Files that could not be blamed, they are not part of the counts
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub errors: Vec<anyhow::Error>,
+}
+
+fn author_name(signature: &git2::Signature) -> String {
+    signature
+        .name()
+        .or(signature.email())
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+// The lines of a file per author who last changed them, None for the binary formats
+fn blame_file(
+    repo: &git2::Repository,
+    commit: &git2::Commit,
+    prefix: &Path,
+    file_path: &Path,
+    id: git2::Oid,
+    options: &CountOptions,
+) -> anyhow::Result<Option<(Language, std::collections::BTreeMap<String, FileInfo>)>> {
+    let blob = repo.find_blob(id)?;
+    let language = identify_content(file_path, blob.content(), options.debug);
+    let Some(kinds) = line_kinds(blob.content(), language) else {
+        return Ok(None);
+    };
+
+    let mut blame_options = git2::BlameOptions::new();
+    blame_options.newest_commit(commit.id());
+    let blame = repo.blame_file(&prefix.join(file_path), Some(&mut blame_options))?;
+
+    let mut by_author = std::collections::BTreeMap::<String, FileInfo>::new();
+    for hunk in blame.iter() {
+        let file_info = by_author
+            .entry(author_name(&hunk.final_signature()))
+            .or_default();
+        let start = hunk.final_start_line().saturating_sub(1);
+        for line in start..start + hunk.lines_in_hunk() {
+            count_line(file_info, kinds.get(line));
+        }
+    }
+    Ok(Some((language, by_author)))
+}
+
+// Attributes every line under `path` at `rev` to the author who last changed it
+pub fn blame(path: &Path, rev: &str, options: &CountOptions) -> anyhow::Result<Authorship> {
+    let (repo, prefix) = open_repository(path)?;
+    let commit = repo
+        .revparse_single(rev)
+        .and_then(|object| object.peel_to_commit())
+        .with_context(|| format!("while resolving {rev}"))?;
+    let tree = tree_at(&repo, rev, &prefix)?;
+
+    let mut files = vec![];
+    tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+        if entry.name_bytes().starts_with(b".") {
+            return git2::TreeWalkResult::Skip;
+        }
+        if entry.kind() == Some(git2::ObjectType::Blob) && entry.filemode() != 0o120000 {
+            let name = String::from_utf8_lossy(entry.name_bytes()).into_owned();
+            files.push((Path::new(dir).join(name), entry.id()));
+        }
+        git2::TreeWalkResult::Ok
+    })?;
+
+    let mut authorship = Authorship::default();
+    for (file_path, id) in files {
+        let blamed = blame_file(&repo, &commit, &prefix, &file_path, id, options)
+            .with_context(|| format!("while blaming {}", file_path.display()));
+        let (language, by_author) = match blamed {
+            Ok(Some(blamed)) => blamed,
+            Ok(None) => continue,
+            Err(err) => {
+                authorship.errors.push(err);
+                continue;
+            }
+        };
+
+        authorship.total.merge_with(&FileInfo::new());
+        for (author, file_info) in by_author {
+            authorship.total.merge_with(&file_info);
+            authorship
+                .by_author
+                .entry((author, language))
+                .or_default()
+                .merge_with(&FileInfo {
+                    file_count: 1,
+                    ..file_info
+                });
+        }
+    }
+
+    Ok(authorship)
+}
+
+// How many commits changed each file under `path`, among the commits of the history of `rev` made
+// since `since`, in seconds since the epoch. Merges are not counted, the commits they bring are.
+// Paths are under `path`, like the ones of a report.
+pub fn churn(path: &Path, rev: &str, since: i64) -> anyhow::Result<HashMap<PathBuf, usize>> {
+    let (repo, prefix) = open_repository(path)?;
+    let head = repo
+        .revparse_single(rev)
+        .and_then(|object| object.peel_to_commit())
+        .with_context(|| format!("while resolving {rev}"))?;
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(git2::Sort::TIME)?;
+    walk.push(head.id())?;
+
+    let mut diff_options = git2::DiffOptions::new();
+    if !prefix.as_os_str().is_empty() {
+        diff_options.pathspec(&prefix);
+    }
+
+    let mut churn = HashMap::new();
+    for id in walk {
+        let commit = repo.find_commit(id?)?;
+        // Newest first, everything after is older
+        if commit.time().seconds() < since {
+            break;
+        }
+        if commit.parent_count() > 1 {
+            continue;
+        }
+
+        let parent_tree = commit
+            .parents()
+            .next()
+            .map(|parent| parent.tree())
+            .transpose()?;
+        let diff = repo.diff_tree_to_tree(
+            parent_tree.as_ref(),
+            Some(&commit.tree()?),
+            Some(&mut diff_options),
+        )?;
+        for delta in diff.deltas() {
+            if delta.status() == git2::Delta::Deleted {
+                continue;
+            }
+            let Some(file_path) = delta.new_file().path() else {
+                continue;
+            };
+            if let Ok(relative) = file_path.strip_prefix(&prefix) {
+                *churn.entry(below(path, relative)).or_default() += 1;
+            }
+        }
+    }
+    Ok(churn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        assert_eq!(split_range("a..b"), ("a", "b", false));
+        assert_eq!(split_range("a.."), ("a", "HEAD", false));
+        assert_eq!(split_range("a"), ("a", "HEAD", false));
+        assert_eq!(split_range("a...b"), ("a", "b", true));
+        assert_eq!(split_range("...b"), ("HEAD", "b", true));
+    }
+}
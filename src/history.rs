@@ -0,0 +1,155 @@
+use std::path::PathBuf;
+
+use loc::{
+    git::{HistoryPoint, Sampling},
+    CountOptions, FileInfo,
+};
+
+use crate::errors::ErrorCollector;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum Period {
+    Day,
+    Week,
+    Tag,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug)]
+pub enum HistoryFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl std::fmt::Display for HistoryFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            HistoryFormat::Csv => "csv",
+            HistoryFormat::Json => "json",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(clap::Args)]
+pub struct HistoryArgs {
+    #[arg(default_value = ".")]
+    path: PathBuf,
+    /// Walk the history of this revision
+    #[arg(long, default_value = "HEAD")]
+    rev: String,
+    /// Count every N commits
+    #[arg(long, value_name = "N", conflicts_with = "by")]
+    every: Option<usize>,
+    /// Count the last commit of every day or week, or every tag
+    #[arg(long)]
+    by: Option<Period>,
+    #[arg(short, long, default_value_t)]
+    format: HistoryFormat,
+}
+
+// `YYYY-MM-DD`, UTC
+fn format_date(time: i64) -> String {
+    // See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = time.div_euclid(24 * 60 * 60);
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+// A CSV field, quoted as in RFC 4180 when it has to be. Tags can hold about anything.
+fn csv_field(field: &str) -> std::borrow::Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
+}
+
+fn write_csv(points: &[HistoryPoint]) {
+    fn row(point: &HistoryPoint, language: &dyn std::fmt::Display, info: &FileInfo) {
+        println!(
+            "{},{},{},{},{},{},{},{},{}",
+            point.commit,
+            format_date(point.time),
+            csv_field(point.tag.as_deref().unwrap_or("")),
+            csv_field(&language.to_string()),
+            info.code,
+            info.comments,
+            info.empty,
+            info.total,
+            info.file_count
+        );
+    }
+
+    println!("commit,date,tag,language,code,comments,empty,total,files");
+    for point in points {
+        for (language, info) in &point.languages {
+            row(point, language, info);
+        }
+        row(point, &"Total", &point.total);
+    }
+}
+
+fn write_json(points: &[HistoryPoint]) -> anyhow::Result<()> {
+    #[derive(serde::Serialize)]
+    struct DatedPoint<'a> {
+        date: String,
+        #[serde(flatten)]
+        point: &'a HistoryPoint,
+    }
+
+    let points: Vec<_> = points
+        .iter()
+        .map(|point| DatedPoint {
+            date: format_date(point.time),
+            point,
+        })
+        .collect();
+    println!("{}", serde_json::to_string_pretty(&points)?);
+    Ok(())
+}
+
+pub fn run(
+    args: HistoryArgs,
+    options: &CountOptions,
+    errors: &mut ErrorCollector,
+) -> anyhow::Result<()> {
+    let sampling = match (args.every, args.by) {
+        (Some(n), _) => Sampling::Commits(n),
+        (None, Some(Period::Day)) => Sampling::Day,
+        (None, Some(Period::Week)) => Sampling::Week,
+        (None, Some(Period::Tag)) => Sampling::Tag,
+        (None, None) => Sampling::Commits(1),
+    };
+
+    let history = loc::git::history(&args.path, &args.rev, sampling, options)?;
+    errors.collect(&history.errors)?;
+    match args.format {
+        HistoryFormat::Csv => write_csv(&history.points),
+        HistoryFormat::Json => write_json(&history.points)?,
+    }
+    errors.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_fields() {
+        assert_eq!(csv_field("v1.0"), "v1.0");
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("a\nb"), "\"a\nb\"");
+    }
+}
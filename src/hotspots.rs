@@ -0,0 +1,119 @@
+use std::{path::PathBuf, time::SystemTime};
+
+use loc::{CountOptions, FileInfo, Language};
+
+use crate::{
+    errors::ErrorCollector,
+    table::{Table, TableDescriptor, TableDescriptorBuilder, TableFormat, TableWrapper},
+    TableFileKey,
+};
+
+#[derive(clap::Args)]
+pub struct HotspotsArgs {
+    #[arg(default_value = ".")]
+    path: PathBuf,
+    /// Count the files and walk the history of this revision
+    #[arg(long, default_value = "HEAD")]
+    rev: String,
+    /// Only count the commits made in this window, like `90.days` or `6.months`
+    #[arg(long, default_value = "90.days", value_parser = parse_window)]
+    since: i64,
+    /// Only show the N highest scores
+    #[arg(long, value_name = "N")]
+    top: Option<usize>,
+}
+
+// A window in seconds, as `<count>.<unit>` with an optional `.ago`, the way git spells it
+fn parse_window(window: &str) -> Result<i64, String> {
+    let window = window.strip_suffix(".ago").unwrap_or(window);
+    let (count, unit) = window
+        .split_once('.')
+        .ok_or_else(|| format!("expected <count>.<unit>, like 90.days, got {window:?}"))?;
+    let count: i64 = count
+        .parse()
+        .map_err(|_| format!("invalid count {count:?}"))?;
+
+    const DAY: i64 = 24 * 60 * 60;
+    let unit = match unit.strip_suffix('s').unwrap_or(unit) {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 60 * 60,
+        "day" => DAY,
+        "week" => 7 * DAY,
+        "month" => 30 * DAY,
+        "year" => 365 * DAY,
+        _ => return Err(format!("unknown unit {unit:?}")),
+    };
+    Ok(count * unit)
+}
+
+struct Hotspot {
+    info: FileInfo,
+    language: Language,
+    commits: usize,
+    // Size times churn
+    score: usize,
+}
+
+struct TableHotspots;
+impl Table for TableHotspots {
+    type Key = TableFileKey;
+    type Value = Hotspot;
+    fn describe() -> TableDescriptor<Self::Value, Self::Key> {
+        TableDescriptorBuilder::column_key_with_format(
+            "File",
+            TableFormat::Left,
+            |x: &TableFileKey| x,
+        )
+        .column("Code", |x: &Hotspot| &x.info.code)
+        .column("Comments", |x: &Hotspot| &x.info.comments)
+        .column("Empty", |x: &Hotspot| &x.info.empty)
+        .column("Total", |x: &Hotspot| &x.info.total)
+        .column_with_format("Commits", TableFormat::Right, |x: &Hotspot| &x.commits)
+        .column_with_format("Score", TableFormat::Right, |x: &Hotspot| &x.score)
+        .column_with_format("Language", TableFormat::Left, |x: &Hotspot| &x.language)
+        .build()
+    }
+}
+
+pub fn run(
+    args: HotspotsArgs,
+    options: &CountOptions,
+    errors: &mut ErrorCollector,
+) -> anyhow::Result<()> {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs() as i64;
+    let mut churn = loc::git::churn(&args.path, &args.rev, now - args.since)?;
+    let report = loc::git::count_rev(&args.path, &args.rev, options)?;
+    errors.collect(&report.errors)?;
+
+    let mut rows: Vec<_> = report
+        .files
+        .into_iter()
+        .filter(|entry| entry.info.textual)
+        .filter_map(|entry| {
+            let commits = churn.remove(&entry.path)?;
+            let hotspot = Hotspot {
+                score: entry.info.code * commits,
+                info: entry.info,
+                language: entry.language,
+                commits,
+            };
+            Some((
+                TableFileKey::Path(entry.path.display().to_string()),
+                hotspot,
+            ))
+        })
+        .collect();
+    rows.sort_by(|(key1, hotspot1), (key2, hotspot2)| {
+        hotspot2
+            .score
+            .cmp(&hotspot1.score)
+            .then_with(|| key1.to_string().cmp(&key2.to_string()))
+    });
+    rows.truncate(args.top.unwrap_or(rows.len()));
+
+    println!("{}", TableWrapper::new::<TableHotspots>(rows.into_iter()));
+    errors.finish()
+}
@@ -1,17 +1,94 @@
-use std::{io::Read, path::Path};
+use std::{
+    borrow::Cow,
+    io::{Read, Seek},
+    path::Path,
+};
 
-use crate::language::Language;
+use crate::{
+    disambiguate::disambiguate,
+    language::Language,
+    modeline::{modeline, shebang_interpreter},
+    registry::custom_language,
+};
+
+// How much of the file is looked at when the extension alone is not enough
+const HEAD_LENGTH: usize = 1024;
+
+// Where the content looked at during identification comes from
+trait Content {
+    fn head(&self, length: usize) -> Cow<'_, [u8]>;
+    fn tail(&self, length: usize) -> Cow<'_, [u8]>;
+}
+
+struct OnDisk<'a>(&'a Path);
+
+impl Content for OnDisk<'_> {
+    fn head(&self, length: usize) -> Cow<'_, [u8]> {
+        let mut buffer = vec![0u8; length];
+        let n = std::fs::File::open(self.0)
+            .and_then(|mut file| file.read(&mut buffer))
+            .unwrap_or(0);
+        buffer.truncate(n);
+        Cow::Owned(buffer)
+    }
+
+    fn tail(&self, length: usize) -> Cow<'_, [u8]> {
+        let mut buffer = vec![];
+        let _ = std::fs::File::open(self.0).and_then(|mut file| {
+            let file_length = file.metadata()?.len();
+            file.seek(std::io::SeekFrom::Start(
+                file_length.saturating_sub(length as u64),
+            ))?;
+            file.read_to_end(&mut buffer)
+        });
+        Cow::Owned(buffer)
+    }
+}
+
+impl Content for [u8] {
+    fn head(&self, length: usize) -> Cow<'_, [u8]> {
+        Cow::Borrowed(&self[..length.min(self.len())])
+    }
+
+    fn tail(&self, length: usize) -> Cow<'_, [u8]> {
+        Cow::Borrowed(&self[self.len().saturating_sub(length)..])
+    }
+}
 
 pub fn identify(path: &Path, debug: bool) -> Language {
-    if let Some(filename) = path.file_name().and_then(|x| x.to_str()) {
-        match filename {
-            "Dockerfile" => return Language::Dockerfile,
-            "CMakeLists.txt" => return Language::CMake,
-            "Makefile" => return Language::Makefile,
-            "LICENSE" => return Language::Txt,
-            "Cargo.lock" => return Language::Txt,
-            _ => (),
+    or_generic(path, try_identify(path), debug)
+}
+
+// Same as `identify`, for content that is not on disk. `path` is only used for its name.
+pub fn identify_content(path: &Path, content: &[u8], debug: bool) -> Language {
+    or_generic(path, identify_with(path, content), debug)
+}
+
+// None when nothing points to a language
+pub(crate) fn try_identify(path: &Path) -> Option<Language> {
+    identify_with(path, &OnDisk(path))
+}
+
+pub(crate) fn or_generic(path: &Path, language: Option<Language>, debug: bool) -> Language {
+    language.unwrap_or_else(|| {
+        if debug {
+            tracing::warn!("unidentified file type with name {}", path.display());
         }
+        Language::Generic
+    })
+}
+
+fn identify_with(path: &Path, content: &(impl Content + ?Sized)) -> Option<Language> {
+    if let Some(language) = custom_language(path) {
+        return Some(language);
+    }
+
+    if let Some(language) = path
+        .file_name()
+        .and_then(|x| x.to_str())
+        .and_then(Language::from_filename)
+    {
+        return Some(language);
     }
 
     if let Some(extension) = path
@@ -19,79 +96,29 @@ pub fn identify(path: &Path, debug: bool) -> Language {
         .and_then(|x| x.to_str())
         .map(|x| x.to_lowercase())
     {
-        match extension.as_str() {
-            "c" | "h" | "cpp" | "hpp" => return Language::C,
-            "zon" => return Language::Zon,
-            "zig" => return Language::Zig,
-            "vert" | "frag" | "glsl" => return Language::Shader,
-            "rs" => return Language::Rust,
-            "py" => return Language::Python,
-            "js" => return Language::Javascript,
-            "ts" | "tsx" => return Language::Typescript,
-            "json" => return Language::Json,
-            "toml" => return Language::Toml,
-            "go" => return Language::Go,
-            "csv" => return Language::Csv,
-            "yaml" | "yml" => return Language::Yaml,
-            "scss" | "css" => return Language::Css,
-            "html" => return Language::Html,
-            "vue" => return Language::VueJs,
-            "svelte" => return Language::Svelte,
-            "md" => return Language::Markdown,
-            "tex" | "bib" => return Language::Tex,
-            "sh" | "bash" | "zsh" | "fish" => return Language::Shell,
-            "txt" => return Language::Txt,
-            "rb" => return Language::Ruby,
-            "liquid" => return Language::Liquid,
-            "slang" => return Language::Slang,
-            "jpg" | "png" | "jpeg" | "gif" | "bmp" | "ttf" | "pdf" | "obj" | "mtl" | "woff"
-            | "woff2" | "o" | "bin" | "gltf" | "out" | "map" | "mp3" => return Language::Asset,
-            "cmake" => return Language::CMake,
-            _ => (),
-        }
-    }
-
-    // By shebang
-    const SHEBANGS: &[(&str, Language)] = &[
-        ("#!/bin/bash", Language::Shell),
-        ("#!/bin/sh", Language::Shell),
-        ("#!/usr/bin/env bash", Language::Shell),
-        ("#!/usr/bin/env sh", Language::Shell),
-        ("#!/usr/bin/env python", Language::Python),
-        ("#!/usr/bin/env python3", Language::Python),
-        ("#!/usr/bin/env node", Language::Javascript),
-        ("#!/usr/bin/env deno", Language::Typescript),
-    ];
-    const MAX_SHEBANG_LENGTH: usize = {
-        let mut max_length = 0;
-        let mut i = 0;
-        // Handroll this loop as const fn doesn't allow iterators or for loops??
-        loop {
-            if i >= SHEBANGS.len() {
-                break;
-            }
-            let shebang = SHEBANGS[i].0;
-            if shebang.len() > max_length {
-                max_length = shebang.len();
+        if Language::is_ambiguous_extension(&extension) {
+            if let Some(language) = disambiguate(&extension, &content.head(HEAD_LENGTH)) {
+                return Some(language);
             }
-            i += 1;
         }
-        max_length
-    };
-    let mut buffer = [0u8; MAX_SHEBANG_LENGTH];
-    if let Ok(mut file) = std::fs::File::open(path) {
-        if let Ok(n) = file.read(&mut buffer) {
-            let content = std::str::from_utf8(&buffer[..n]).unwrap_or("");
-            for (shebang, language) in SHEBANGS {
-                if content.starts_with(shebang) {
-                    return *language;
-                }
-            }
+        if let Some(language) = Language::from_extension(&extension) {
+            return Some(language);
         }
     }
 
-    if debug {
-        println!("unidentified file type with name {}", path.display())
+    // By shebang or editor modeline
+    let head = content.head(HEAD_LENGTH);
+    let head = String::from_utf8_lossy(&head);
+    let first_line = head.lines().next().unwrap_or("");
+    if let Some(language) = shebang_interpreter(first_line).and_then(Language::from_interpreter) {
+        return Some(language);
+    }
+
+    let tail = content.tail(HEAD_LENGTH);
+    let tail = String::from_utf8_lossy(&tail);
+    if let Some(language) = modeline(&head, &tail).and_then(|mode| Language::from_mode(&mode)) {
+        return Some(language);
     }
-    Language::Generic
+
+    None
 }
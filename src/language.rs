@@ -1,106 +1,65 @@
-use crate::line_kind::{Generic, GenericWithComment, LineKindEstimator, MultilineCommentAware};
+use crate::{
+    line_kind::{Generic, GenericWithComment, LineKindEstimator, MultilineCommentAware},
+    registry::make_registered_estimator,
+};
 
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
-pub enum Language {
-    Rust,
-    C,
-    Zig,
-    Zon,
-    Javascript,
-    Typescript,
-    Json,
-    Python,
-    Generic,
-    Toml,
-    Go,
-    Yaml,
-    Markdown,
-    VueJs,
-    Css,
-    Csv,
-    Dockerfile,
-    Shader,
-    CMake,
-    Makefile,
-    Asset,
-    Tex,
-    Liquid,
-    Ruby,
-    Html,
-    Shell,
-    Txt,
-    Slang,
-    Lockfile,
-    Svelte,
-}
+// The `Language` enum and its lookup tables are generated from languages.toml by the build script
+include!(concat!(env!("OUT_DIR"), "/languages.rs"));
 
 impl std::fmt::Display for Language {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let this = match self {
-            Language::Rust => "Rust",
-            Language::C => "C/C++",
-            Language::Zig => "Zig",
-            Language::Zon => "Zon",
-            Language::Generic => "Other",
-            Language::Javascript => "Javascript",
-            Language::Typescript => "Typescript",
-            Language::Json => "JSON",
-            Language::Python => "Python",
-            Language::Toml => "TOML",
-            Language::Go => "GO",
-            Language::Yaml => "YAML",
-            Language::Markdown => "Markdown",
-            Language::VueJs => "VueJs",
-            Language::Css => "CSS",
-            Language::Html => "HTML",
-            Language::Csv => "CSV",
-            Language::Dockerfile => "Dockerfile",
-            Language::Shader => "Shader",
-            Language::CMake => "CMake",
-            Language::Asset => "Asset",
-            Language::Makefile => "Makefile",
-            Language::Tex => "Tex/Latex",
-            Language::Txt => "Text",
-            Language::Shell => "Shell",
-            Language::Ruby => "Ruby",
-            Language::Liquid => "Liquid",
-            Language::Lockfile => "Lockfile",
-            Language::Slang => "Slang",
-            Language::Svelte => "Svelte",
-        };
-        write!(f, "{this}")
+        write!(f, "{}", self.name())
+    }
+}
+
+// Languages are serialized as their id, so that renaming one does not break saved reports
+#[cfg(feature = "serde")]
+impl serde::Serialize for Language {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.id())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Language {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // The ids of the built-in languages, for error messages
+        static IDS: std::sync::OnceLock<Vec<&'static str>> = std::sync::OnceLock::new();
+
+        let id = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        // Other ids have to be registered at runtime before loading, a typo is an error
+        Language::from_id(&id)
+            .or_else(|| crate::registry::registered_language(&id))
+            .ok_or_else(|| {
+                let ids = IDS.get_or_init(|| Language::ALL.iter().map(|x| x.id()).collect());
+                <D::Error as serde::de::Error>::unknown_variant(&id, ids)
+            })
     }
 }
 
 pub fn make_line_kind_estimator(language: Language) -> Option<Box<dyn LineKindEstimator + Send>> {
-    match language {
-        Language::Rust | Language::Zig | Language::Zon => {
-            Some(Box::new(GenericWithComment::new("//")))
-        }
-        Language::VueJs
-        | Language::Slang
-        | Language::C
-        | Language::Javascript
-        | Language::Typescript
-        | Language::Go
-        | Language::Svelte
-        | Language::Shader => Some(Box::new(MultilineCommentAware::new("//", ["/*", "*/"]))),
-        Language::Python | Language::Toml => Some(Box::new(GenericWithComment::new("#"))),
-        Language::Tex => Some(Box::new(GenericWithComment::new("%"))),
-        Language::Markdown
-        | Language::Makefile
-        | Language::Css
-        | Language::Yaml
-        | Language::Html
-        | Language::Csv
-        | Language::Liquid
-        | Language::Dockerfile
-        | Language::Generic
-        | Language::CMake
-        | Language::Ruby
-        | Language::Txt
-        | Language::Shell
-        | Language::Json => Some(Box::new(Generic)),
-        Language::Lockfile | Language::Asset => None,
+    if let Some(estimator) = make_registered_estimator(language) {
+        return Some(estimator);
+    }
+    if language.is_binary() {
+        return None;
+    }
+
+    let line_comments = language.line_comments();
+    match language.block_comment() {
+        Some(block_comment) => Some(Box::new(MultilineCommentAware::new(
+            line_comments,
+            block_comment,
+        ))),
+        None if !line_comments.is_empty() => Some(Box::new(GenericWithComment::new(line_comments))),
+        None => Some(Box::new(Generic)),
+    }
+}
+
+// Ignores block comments, to recount the files where one is never closed
+pub(crate) fn make_line_comment_estimator(language: Language) -> Box<dyn LineKindEstimator + Send> {
+    match language.line_comments() {
+        [] => Box::new(Generic),
+        line_comments => Box::new(GenericWithComment::new(line_comments)),
     }
 }
@@ -0,0 +1,24 @@
+#[cfg(feature = "async")]
+pub mod asynchronous;
+mod count;
+pub mod diagnostic;
+mod disambiguate;
+pub mod file_info;
+#[cfg(feature = "git")]
+pub mod git;
+pub mod identify;
+pub mod language;
+pub mod line_kind;
+mod modeline;
+pub mod registry;
+
+pub use count::{count_paths, CountOptions, FileEntry, Report, Summary};
+pub use diagnostic::{Diagnostic, DiagnosticKind};
+pub use file_info::{
+    file_info_from_bytes, file_info_from_path, file_info_from_reader, file_info_from_str,
+    gen_file_info, gen_file_info_from_bytes, gen_file_info_from_reader, FileInfo, LanguageHint,
+};
+pub use identify::{identify, identify_content};
+pub use language::{make_line_kind_estimator, Language};
+pub use line_kind::{LineKind, LineKindEstimator};
+pub use registry::{register_estimator, register_language};
@@ -1,3 +1,5 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LineKind {
     Comment,
     Code,
@@ -6,58 +8,87 @@ pub enum LineKind {
 
 pub trait LineKindEstimator {
     fn estimate(&mut self, line: &str) -> LineKind;
+
+    // Whether the last line left a block comment open. Still true at the end of a file, the
+    // comment was never closed and everything after its opening was counted as comments.
+    fn in_block_comment(&self) -> bool {
+        false
+    }
 }
 
 pub struct MultilineCommentAware {
-    comment: &'static str,
+    comments: &'static [&'static str],
     multi_line_comment: [&'static str; 2],
     is_in_multiline_comment: bool,
 }
 
 impl MultilineCommentAware {
-    pub fn new(comment: &'static str, multi_line_comment: [&'static str; 2]) -> Self {
+    pub fn new(comments: &'static [&'static str], multi_line_comment: [&'static str; 2]) -> Self {
         Self {
-            comment,
+            comments,
             multi_line_comment,
             is_in_multiline_comment: false,
         }
     }
 }
 
+impl MultilineCommentAware {
+    // Classifies what follows the opening of a block comment, which may be closed on the same
+    // line with code after it
+    fn estimate_block_comment(&mut self, mut rest: &str) -> LineKind {
+        let [open, close] = self.multi_line_comment;
+        loop {
+            let Some(end) = rest.find(close) else {
+                self.is_in_multiline_comment = true;
+                return LineKind::Comment;
+            };
+            rest = rest[end + close.len()..].trim_start();
+            if rest.is_empty() || self.comments.iter().any(|c| rest.starts_with(c)) {
+                return LineKind::Comment;
+            }
+            match rest.strip_prefix(open) {
+                Some(next) => rest = next,
+                None => return LineKind::Code,
+            }
+        }
+    }
+}
+
 impl LineKindEstimator for MultilineCommentAware {
     fn estimate(&mut self, line: &str) -> LineKind {
         let trimmed = line.trim();
-        match self.is_in_multiline_comment {
-            true => {
-                if trimmed.ends_with(self.multi_line_comment[1]) {
-                    self.is_in_multiline_comment = false;
-                }
-                LineKind::Comment
-            }
-            false => {
-                let trimmed = line.trim();
-                if trimmed.is_empty() {
-                    LineKind::Empty
-                } else if trimmed.starts_with(self.comment) {
-                    LineKind::Comment
-                } else if trimmed.starts_with(self.multi_line_comment[0]) {
-                    self.is_in_multiline_comment = true;
-                    LineKind::Comment
-                } else {
-                    LineKind::Code
-                }
-            }
+        let open = self.multi_line_comment[0];
+        if self.is_in_multiline_comment {
+            self.is_in_multiline_comment = false;
+            return self.estimate_block_comment(trimmed);
         }
+
+        if trimmed.is_empty() {
+            LineKind::Empty
+        } else if self.comments.iter().any(|c| {
+            // Unless the opening of the multiline comment starts with it, as in lua: `--[[`
+            trimmed.starts_with(c) && !(open.starts_with(c) && trimmed.starts_with(open))
+        }) {
+            LineKind::Comment
+        } else if let Some(rest) = trimmed.strip_prefix(open) {
+            self.estimate_block_comment(rest)
+        } else {
+            LineKind::Code
+        }
+    }
+
+    fn in_block_comment(&self) -> bool {
+        self.is_in_multiline_comment
     }
 }
 
 pub struct GenericWithComment {
-    comment: &'static str,
+    comments: &'static [&'static str],
 }
 
 impl GenericWithComment {
-    pub fn new(comment: &'static str) -> Self {
-        Self { comment }
+    pub fn new(comments: &'static [&'static str]) -> Self {
+        Self { comments }
     }
 }
 
@@ -66,7 +97,7 @@ impl LineKindEstimator for GenericWithComment {
         let trimmed = line.trim();
         if trimmed.is_empty() {
             LineKind::Empty
-        } else if trimmed.starts_with(self.comment) {
+        } else if self.comments.iter().any(|c| trimmed.starts_with(c)) {
             LineKind::Comment
         } else {
             LineKind::Code
@@ -85,3 +116,56 @@ impl LineKindEstimator for Generic {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimate_all(mut estimator: impl LineKindEstimator, lines: &[&str]) -> Vec<LineKind> {
+        lines.iter().map(|line| estimator.estimate(line)).collect()
+    }
+
+    #[test]
+    fn block_comments() {
+        use LineKind::*;
+
+        let c = || MultilineCommentAware::new(&["//"], ["/*", "*/"]);
+        assert_eq!(
+            estimate_all(c(), &["/* header */ int x = 1;", "int y = 2;", "", "// z"]),
+            [Code, Code, Empty, Comment],
+        );
+        assert_eq!(
+            estimate_all(c(), &["/* a */", "/* b */ // c", "/* d */ /* e */", "x;"]),
+            [Comment, Comment, Comment, Code],
+        );
+        assert_eq!(
+            estimate_all(c(), &["/*", " * a */ int x;", "/* b", "c */", "x;"]),
+            [Comment, Code, Comment, Comment, Code],
+        );
+        assert_eq!(
+            estimate_all(c(), &["/**", " * a", " */", "int x;"]),
+            [Comment, Comment, Comment, Code],
+        );
+
+        let mut estimator = c();
+        estimator.estimate("/* a */ x; /* b */");
+        assert!(!estimator.in_block_comment());
+        estimator.estimate("/* a");
+        assert!(estimator.in_block_comment());
+    }
+
+    #[test]
+    fn block_comment_starting_with_line_comment() {
+        use LineKind::*;
+
+        let lua = || MultilineCommentAware::new(&["--"], ["--[[", "]]"]);
+        assert_eq!(
+            estimate_all(lua(), &["--[[", "a", "]]", "-- b", "x = 1"]),
+            [Comment, Comment, Comment, Comment, Code],
+        );
+        assert_eq!(
+            estimate_all(lua(), &["--[[ a ]] x = 1", "--[[ b ]]", "x = 2"]),
+            [Code, Comment, Code],
+        );
+    }
+}
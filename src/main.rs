@@ -1,19 +1,24 @@
-mod file_info;
-mod identify;
-mod language;
-mod line_kind;
+mod author;
+mod check;
+mod compare;
+mod diff;
+mod errors;
+mod history;
+mod hotspots;
+mod ratios;
+mod sort;
 mod table;
 
-use std::{collections::HashMap, fmt::Display, path::PathBuf};
+use std::{fmt::Display, path::PathBuf, process::ExitCode};
 
 use crate::{
-    file_info::{file_info_from_path, FileInfo},
-    language::Language,
+    errors::{ErrorCollector, UsageError},
     table::TableWrapper,
 };
 use anyhow::Context;
 use clap::Parser;
-use futures::StreamExt;
+use loc::{count_paths, CountOptions, FileInfo, Language};
+use sort::{info_number, SortArgs, SortKey, Sortable};
 use table::{Table, TableDescriptor, TableDescriptorBuilder, TableFormat};
 use tracing::level_filters::LevelFilter;
 use tracing_subscriber::{
@@ -21,61 +26,173 @@ use tracing_subscriber::{
 };
 
 #[derive(clap::ValueEnum, Clone, Copy, Default, Debug)]
-pub enum SortKey {
+pub enum Mode {
     #[default]
-    Code,
-    Total,
     Language,
     File,
+    // Lines per last author, from git blame
+    Author,
 }
-
-impl Display for SortKey {
+impl Display for Mode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let name = match self {
-            SortKey::Code => "code",
-            SortKey::Language => "language",
-            SortKey::Total => "total",
-            SortKey::File => "file",
+            Mode::Language => "language",
+            Mode::File => "file",
+            Mode::Author => "author",
         };
 
         write!(f, "{}", name)
     }
 }
 
-#[derive(clap::ValueEnum, Clone, Copy, Default, Debug)]
-pub enum Mode {
-    #[default]
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    Code,
+    Comments,
+    Empty,
+    Total,
+    Files,
     Language,
-    File,
+    // Share of the total code
+    Pct,
+    // Comments over code and comments
+    Density,
+    // Blank lines over all lines
+    Blank,
+    // Lines per file
+    Average,
 }
-impl Display for Mode {
+
+impl Column {
+    // The header of the column in the tables
+    fn header(self) -> &'static str {
+        match self {
+            Column::Code => "Code",
+            Column::Comments => "Comments",
+            Column::Empty => "Empty",
+            Column::Total => "Total",
+            Column::Files => "File count",
+            Column::Language => "Language",
+            Column::Pct => "Code share",
+            Column::Density => "Comment density",
+            Column::Blank => "Blank ratio",
+            Column::Average => "Lines per file",
+        }
+    }
+}
+
+impl Display for Column {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let name = match self {
-            Mode::Language => "language",
-            Mode::File => "file",
+            Column::Code => "code",
+            Column::Comments => "comments",
+            Column::Empty => "empty",
+            Column::Total => "total",
+            Column::Files => "files",
+            Column::Language => "language",
+            Column::Pct => "pct",
+            Column::Density => "density",
+            Column::Blank => "blank",
+            Column::Average => "average",
         };
 
         write!(f, "{}", name)
     }
 }
 
+// Keeps the given columns of a table, in that order, or all of them without a choice
+pub fn select_columns<V, K>(
+    descriptor: TableDescriptor<V, K>,
+    columns: Option<&[Column]>,
+) -> anyhow::Result<TableDescriptor<V, K>> {
+    let Some(columns) = columns else {
+        return Ok(descriptor);
+    };
+    for (i, column) in columns.iter().enumerate() {
+        if columns[..i].contains(column) {
+            anyhow::bail!(UsageError(format!("The {column} column is given twice")));
+        }
+    }
+    let headers: Vec<_> = columns.iter().map(|column| column.header()).collect();
+    descriptor.select(&headers).map_err(|header| {
+        let column = columns
+            .iter()
+            .find(|column| column.header() == header)
+            .expect("only the given headers are selected");
+        UsageError(format!("There is no {column} column in this table")).into()
+    })
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Count the code over the git history, as CSV or JSON
+    History(history::HistoryArgs),
+    /// Classify the lines added and removed between two git revisions
+    Diff(diff::DiffArgs),
+    /// Fail when the counts break the limits of a policy file
+    Check(check::CheckArgs),
+    /// Rank the files changed recently by size times number of commits
+    Hotspots(hotspots::HotspotsArgs),
+}
+
 #[derive(clap::Parser)]
+#[command(
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    after_help = errors::EXIT_STATUS_HELP
+)]
 struct Args {
-    path: PathBuf,
-    #[arg(short, long = "sort-by", visible_alias = "sort", default_value_t)]
-    sort: SortKey,
-    #[arg(short, long, default_value_t)]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(required = true)]
+    path: Option<PathBuf>,
+    #[command(flatten)]
+    sort: SortArgs,
+    #[arg(short, long, default_value_t, global = true)]
     debug: bool,
 
-    #[arg(short, long, action = clap::ArgAction::Count)]
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
     verbose: u8,
+    /// Fail without printing anything when a file cannot be counted
+    #[arg(long, global = true)]
+    strict: bool,
 
     #[arg(short, long, default_value_t)]
     mode: Mode,
+
+    /// Count the files as they are at the given git revision, without checking it out
+    #[arg(long, value_name = "COMMIT")]
+    rev: Option<String>,
+    /// Only count the files tracked in the git index, or walk the disk outside of a repository
+    #[arg(long, conflicts_with = "rev")]
+    git_tracked: bool,
+
+    /// Write what was odd about the counted files to this file, as JSON lines
+    #[arg(long, value_name = "FILE", conflicts_with = "rev")]
+    diagnostics: Option<PathBuf>,
+
+    /// Add the share of the total code, the comment density and the ratio of blank lines, and the
+    /// lines per file by language
+    #[arg(long)]
+    ratios: bool,
+    /// The columns to show, in that order, after the file or language
+    #[arg(long, value_delimiter = ',')]
+    columns: Option<Vec<Column>>,
+
+    /// Recount the files ending inside a block comment as if their language had none
+    #[arg(long)]
+    recount_unterminated: bool,
+
+    /// Save the counts as a JSON snapshot to compare later runs against
+    #[arg(long, value_name = "FILE")]
+    save_baseline: Option<PathBuf>,
+    /// Show the changes since the given JSON snapshot instead of the counts
+    #[arg(long, value_name = "FILE")]
+    compare: Option<PathBuf>,
 }
 
-#[tokio::main]
-async fn main() -> std::io::Result<()> {
+fn main() -> ExitCode {
     let args = Args::parse();
 
     let reg = tracing_subscriber::registry();
@@ -93,6 +210,7 @@ async fn main() -> std::io::Result<()> {
 
     let reg = reg.with(
         tracing_subscriber::fmt::layer()
+            .with_writer(std::io::stderr)
             .with_timer(tracing_subscriber::fmt::time::uptime())
             .with_span_events(FmtSpan::CLOSE)
             .with_filter(filter),
@@ -100,96 +218,148 @@ async fn main() -> std::io::Result<()> {
 
     reg.init();
 
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err:#}");
+            errors::exit_code(&err)
+        }
+    }
+}
+
+fn run(args: Args) -> anyhow::Result<()> {
+    let mut errors = ErrorCollector::new(args.strict);
+    let options = CountOptions {
+        debug: args.debug,
+        git_tracked: args.git_tracked,
+        recount_unterminated: args.recount_unterminated,
+    };
+    if let Some(command) = args.command {
+        return match command {
+            Command::History(history_args) => history::run(history_args, &options, &mut errors),
+            Command::Diff(diff_args) => diff::run(diff_args, &options, &mut errors),
+            Command::Check(check_args) => check::run(check_args, &options, &mut errors),
+            Command::Hotspots(hotspots_args) => hotspots::run(hotspots_args, &options, &mut errors),
+        };
+    }
+    let path = args
+        .path
+        .expect("the path is required without a subcommand");
+
+    if let Mode::Author = args.mode {
+        // Authors come from blame, there is no count of the files to save, compare or look into
+        let unsupported = [
+            ("--compare", args.compare.is_some()),
+            ("--save-baseline", args.save_baseline.is_some()),
+            ("--diagnostics", args.diagnostics.is_some()),
+            ("--ratios", args.ratios),
+            ("--git-tracked", args.git_tracked),
+            ("--recount-unterminated", args.recount_unterminated),
+        ];
+        if let Some((flag, _)) = unsupported.iter().find(|(_, given)| *given) {
+            anyhow::bail!(UsageError(format!(
+                "{flag} cannot be used with the author mode"
+            )));
+        }
+        return author::run(
+            &path,
+            args.rev.as_deref(),
+            &args.sort,
+            args.columns.as_deref(),
+            &options,
+            &mut errors,
+        );
+    }
+
+    // The comparison tables have no ratio columns
+    if args.ratios && args.compare.is_some() {
+        anyhow::bail!(UsageError(
+            "--ratios cannot be used with --compare".to_string()
+        ));
+    }
+
     tracing::debug!("Starting to walk the directory...");
-    tracing::debug!("Using path: {}", args.path.display());
-
-    let walker = ignore::WalkBuilder::new(args.path)
-        .hidden(true)
-        // .filter_entry(|x| !x.path().is_dir())
-        .build();
-
-    let mut file_infos = futures::stream::iter(walker)
-        .then(|file| async {
-            let f = file?;
-            if f.path().is_dir() {
-                return Ok(None);
-            }
-            let (i, p) = file_info_from_path(f.path(), args.debug)
-                .await
-                .with_context(|| format!("while getting file infos from {}", f.path().display()))?;
-            anyhow::Ok(Some((f.path().to_path_buf(), i, p)))
-        })
-        .boxed();
+    tracing::debug!("Using path: {}", path.display());
+
+    let report = match &args.rev {
+        Some(rev) => loc::git::count_rev(&path, rev, &options)?,
+        None => count_paths([&path], &options),
+    };
+    if let Some(path) = &args.diagnostics {
+        write_diagnostics(&report.diagnostics, path)?;
+    }
+    // Their comment counts are most likely wrong, they deserve a look
+    for diagnostic in &report.diagnostics {
+        if diagnostic.kind == loc::DiagnosticKind::UnterminatedBlockComment {
+            tracing::warn!(
+                "suspicious file {}: {}",
+                diagnostic.path.display(),
+                diagnostic.detail.as_deref().unwrap_or_default()
+            );
+        }
+    }
+    errors.collect(&report.errors)?;
+    let loc_total = report.total();
+    let total_code = loc_total.code;
+    // Picking one of the ratio columns is asking for them
+    let with_ratios = args.ratios || args.columns.is_some();
+
+    let baseline = args
+        .compare
+        .as_deref()
+        .map(compare::load_baseline)
+        .transpose()?;
+    if args.save_baseline.is_some() || baseline.is_some() {
+        let summary = compare::snapshot(&report, &path);
+        if let Some(path) = &args.save_baseline {
+            compare::save_baseline(&summary, path)?;
+        }
+        if let Some(baseline) = &baseline {
+            compare::print_comparison(
+                baseline,
+                &summary,
+                args.mode,
+                &args.sort,
+                args.columns.as_deref(),
+            )?;
+            return errors.finish();
+        }
+    }
 
     match args.mode {
         Mode::Language => {
-            let mut loc_total = FileInfo::default();
-            let mut loc_by_lang = HashMap::<Language, FileInfo>::new();
-            while let Some(next_file_info) = file_infos.next().await {
-                match next_file_info {
-                    Ok(Some((_, file_info, language))) => {
-                        loc_by_lang
-                            .entry(language)
-                            .or_default()
-                            .merge_with(&file_info);
-                        loc_total.merge_with(&file_info);
-                    }
-                    Ok(None) => (),
-                    Err(err) => {
-                        println!("ERROR! {err:#}");
-                    }
-                }
-            }
-
+            let loc_by_lang = report.by_language();
             let mut rows: Vec<_> = loc_by_lang
                 .into_iter()
                 .map(|(x, y)| (TableKey::Language(x), y))
                 .collect();
 
-            match args.sort {
-                SortKey::Language => rows.sort_by_key(|(key, _)| key.to_string()),
-                SortKey::Code => rows.sort_by_key(|fileinfo| -(fileinfo.1.code as isize)),
-                SortKey::Total => rows.sort_by(|(_, fileinfo1), (_, fileinfo2)| {
-                    fileinfo2.total.cmp(&fileinfo1.total)
-                }),
-                SortKey::File => {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::InvalidInput,
-                        "Cannot sort by file when mode is language",
-                    ))
-                }
-            };
+            args.sort.sort(&mut rows)?;
 
             let rows_iter = rows
                 .into_iter()
                 .chain(std::iter::once((TableKey::Total, loc_total)));
-            println!("{}", TableWrapper::new::<TableByLanguage>(rows_iter));
+            let mut descriptor = TableByLanguage::describe();
+            if with_ratios {
+                descriptor = ratios::with_ratios(descriptor, total_code, |x: &FileInfo| x);
+                descriptor = ratios::with_average_lines(descriptor, |x: &FileInfo| x);
+            }
+            let descriptor = select_columns(descriptor, args.columns.as_deref())?;
+            println!("{}", TableWrapper::with_descriptor(descriptor, rows_iter));
         }
         Mode::File => {
-            let mut loc_total = FileInfo::default();
-            let mut loc_per_file = HashMap::<String, (FileInfo, Language)>::new();
-            while let Some(next_file_info) = file_infos.next().await {
-                match next_file_info {
-                    Ok(Some((path, file_info, language))) => {
-                        loc_total.merge_with(&file_info);
-                        loc_per_file.insert(path.display().to_string(), (file_info, language));
-                    }
-                    Ok(None) => (),
-                    Err(err) => {
-                        println!("ERROR! {err:#}");
-                    }
-                }
-            }
-            let mut rows: Vec<_> = loc_per_file
+            let mut rows: Vec<_> = report
+                .files
                 .into_iter()
-                .map(|(path, (info, language))| (TableFileKey::Path(path), info, language))
+                .map(|entry| {
+                    (
+                        TableFileKey::Path(entry.path.display().to_string()),
+                        entry.info,
+                        entry.language,
+                    )
+                })
                 .collect();
-            match args.sort {
-                SortKey::Language => rows.sort_by_key(|(_, _, language)| language.to_string()),
-                SortKey::Code => rows.sort_by_key(|(_, info, _)| -(info.code as isize)),
-                SortKey::Total => rows.sort_by_key(|(_, info, _)| -(info.total as isize)),
-                SortKey::File => rows.sort_by_key(|(key, _, _)| key.to_string()),
-            };
+            args.sort.sort(&mut rows)?;
 
             let rows_iter = rows
                 .into_iter()
@@ -200,11 +370,34 @@ async fn main() -> std::io::Result<()> {
                 TableFileValue::Total(loc_total),
             )));
 
-            println!("{}", TableWrapper::new::<TableFile>(rows_iter));
+            let mut descriptor = TableFile::describe();
+            if with_ratios {
+                descriptor = ratios::with_ratios(descriptor, total_code, TableFileValue::info);
+            }
+            let descriptor = select_columns(descriptor, args.columns.as_deref())?;
+            println!("{}", TableWrapper::with_descriptor(descriptor, rows_iter));
         }
+        Mode::Author => unreachable!("author mode does not count the working tree"),
     }
 
-    Ok(())
+    errors.finish()
+}
+
+fn write_diagnostics(
+    diagnostics: &[loc::Diagnostic],
+    path: &std::path::Path,
+) -> anyhow::Result<()> {
+    use std::io::Write;
+
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("while creating {}", path.display()))?;
+    let mut out = std::io::BufWriter::new(file);
+    for diagnostic in diagnostics {
+        serde_json::to_writer(&mut out, diagnostic)?;
+        writeln!(out)?;
+    }
+    out.flush()
+        .with_context(|| format!("while writing {}", path.display()))
 }
 
 #[derive(Debug, Clone)]
@@ -226,6 +419,13 @@ pub enum TableFileValue {
     Total(FileInfo),
 }
 impl TableFileValue {
+    fn info(&self) -> &FileInfo {
+        match self {
+            TableFileValue::File { info, .. } => info,
+            TableFileValue::Total(info) => info,
+        }
+    }
+
     fn display_code(&self) -> &dyn Display {
         match self {
             TableFileValue::File { info, .. } => get_or_default(info, &info.code),
@@ -260,6 +460,41 @@ impl TableFileValue {
             TableFileValue::Total(_) => &"-",
         }
     }
+
+    fn display_file_count(&self) -> &dyn Display {
+        match self {
+            TableFileValue::File { info, .. } => &info.file_count,
+            TableFileValue::Total(info) => &info.file_count,
+        }
+    }
+}
+
+impl Sortable for (TableKey, FileInfo) {
+    fn name(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn language(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn number(&self, key: SortKey) -> Option<f64> {
+        info_number(&self.1, key)
+    }
+}
+
+impl Sortable for (TableFileKey, FileInfo, Language) {
+    fn name(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn language(&self) -> String {
+        self.2.to_string()
+    }
+
+    fn number(&self, key: SortKey) -> Option<f64> {
+        info_number(&self.1, key)
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -0,0 +1,134 @@
+// Parsing of the hints a file can carry about its own language: shebangs and editor modelines
+
+// Vim only looks at the first and last few lines for modelines, we do the same
+const MODELINE_LINES: usize = 5;
+
+// `#!/usr/bin/env -S python3 -u` -> `python`, `#!/usr/local/bin/bash` -> `bash`
+pub fn shebang_interpreter(first_line: &str) -> Option<&str> {
+    let mut args = first_line.strip_prefix("#!")?.split_whitespace();
+    let mut interpreter = basename(args.next()?);
+
+    if interpreter == "env" {
+        interpreter = loop {
+            let arg = args.next()?;
+            match arg {
+                // Those options take a value
+                "-u" | "--unset" | "-C" | "--chdir" => {
+                    args.next()?;
+                }
+                _ if arg.starts_with('-') || arg.contains('=') => (),
+                _ => break basename(arg),
+            }
+        };
+    }
+
+    // python3.11 -> python
+    let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    (!interpreter.is_empty()).then_some(interpreter)
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+pub fn modeline(head: &str, tail: &str) -> Option<String> {
+    let head_lines = head.lines().take(MODELINE_LINES);
+    let tail_lines = tail.lines().rev().take(MODELINE_LINES);
+
+    // Emacs only honours its header on the first line, or the second one after a shebang
+    head.lines()
+        .take(2)
+        .find_map(emacs_mode)
+        .or_else(|| head_lines.chain(tail_lines).find_map(vim_filetype))
+        .map(|mode| mode.to_lowercase())
+}
+
+// `# vim: set ft=ruby:`, `// vi: filetype=c sw=4`
+fn vim_filetype(line: &str) -> Option<&str> {
+    let (_, options) = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        let (before, after) = line.split_once(marker)?;
+        // The marker must be preceded by a blank, or be at the start of the line
+        (before.is_empty() || before.ends_with(char::is_whitespace)).then_some((before, after))
+    })?;
+
+    options
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .find_map(|option| {
+            let (key, value) = option.split_once('=')?;
+            matches!(key, "ft" | "filetype" | "syn" | "syntax").then_some(value)
+        })
+        .filter(|value| !value.is_empty())
+}
+
+// `# -*- mode: python -*-`, `/* -*- c++ -*- */`
+fn emacs_mode(line: &str) -> Option<&str> {
+    let (_, rest) = line.split_once("-*-")?;
+    let (variables, _) = rest.split_once("-*-")?;
+
+    let mode = if variables.contains(':') {
+        variables.split(';').find_map(|variable| {
+            let (key, value) = variable.split_once(':')?;
+            key.trim().eq_ignore_ascii_case("mode").then_some(value)
+        })?
+    } else {
+        variables
+    };
+
+    let mode = mode.trim();
+    let mode = mode.strip_suffix("-mode").unwrap_or(mode);
+    (!mode.is_empty()).then_some(mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shebangs() {
+        assert_eq!(shebang_interpreter("#!/usr/local/bin/bash"), Some("bash"));
+        assert_eq!(
+            shebang_interpreter("#!/usr/bin/env -S python3 -u"),
+            Some("python")
+        );
+        assert_eq!(
+            shebang_interpreter("#!/usr/bin/env python3.11"),
+            Some("python")
+        );
+        assert_eq!(
+            shebang_interpreter("#!/usr/bin/env -u HOME LANG=C node"),
+            Some("node")
+        );
+        assert_eq!(shebang_interpreter("#!/usr/bin/env"), None);
+        assert_eq!(shebang_interpreter("# not a shebang"), None);
+    }
+
+    #[test]
+    fn vim_modelines() {
+        assert_eq!(vim_filetype("# vim: set ft=ruby:"), Some("ruby"));
+        assert_eq!(vim_filetype("// vi: filetype=c sw=4"), Some("c"));
+        assert_eq!(vim_filetype("/* ex: syntax=sh */"), Some("sh"));
+        assert_eq!(vim_filetype("# vim: set sw=4:"), None);
+        assert_eq!(vim_filetype("let svim: ft=ruby"), None);
+    }
+
+    #[test]
+    fn emacs_modelines() {
+        assert_eq!(emacs_mode("# -*- mode: python -*-"), Some("python"));
+        assert_eq!(emacs_mode("/* -*- c++ -*- */"), Some("c++"));
+        assert_eq!(
+            emacs_mode(";; -*- coding: utf-8; mode: emacs-lisp-mode -*-"),
+            Some("emacs-lisp")
+        );
+        assert_eq!(emacs_mode("# -*- coding: utf-8 -*-"), None);
+        assert_eq!(emacs_mode("# -*- mode: python"), None);
+    }
+
+    #[test]
+    fn modelines() {
+        let head = "#!/usr/bin/env ruby\n# -*- mode: Python -*-\n";
+        assert_eq!(modeline(head, head).as_deref(), Some("python"));
+        let tail = "x = 1\n# vim: set ft=Ruby:\n";
+        assert_eq!(modeline("x = 1\n", tail).as_deref(), Some("ruby"));
+        assert_eq!(modeline("x = 1\n", "x = 1\n"), None);
+    }
+}
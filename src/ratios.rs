@@ -0,0 +1,71 @@
+use std::fmt::Display;
+
+use loc::FileInfo;
+
+use crate::table::{TableDescriptor, TableFormat};
+
+// A share of a whole, as a percentage. A share of nothing is shown as "-", like the counts of
+// binary files.
+pub struct Percent(Option<f64>);
+
+impl Percent {
+    pub fn of(part: usize, whole: usize) -> Self {
+        Percent((whole != 0).then(|| 100. * part as f64 / whole as f64))
+    }
+
+    // Binary files have no lines to take a share of
+    fn of_lines(info: &FileInfo, part: usize, whole: usize) -> Self {
+        match info.textual {
+            true => Self::of(part, whole),
+            false => Percent(None),
+        }
+    }
+}
+
+impl Display for Percent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(percent) => write!(f, "{percent:.1}%"),
+            None => write!(f, "-"),
+        }
+    }
+}
+
+// The share of all the code, the comment density (comments over lines with something in them)
+// and the share of blank lines
+pub fn with_ratios<V: 'static, K>(
+    descriptor: TableDescriptor<V, K>,
+    total_code: usize,
+    info: fn(&V) -> &FileInfo,
+) -> TableDescriptor<V, K> {
+    descriptor
+        .into_builder()
+        .computed_column_with_format("Code share", TableFormat::Right, move |x: &V| {
+            Percent::of_lines(info(x), info(x).code, total_code)
+        })
+        .computed_column_with_format("Comment density", TableFormat::Right, move |x: &V| {
+            let info = info(x);
+            Percent::of_lines(info, info.comments, info.code + info.comments)
+        })
+        .computed_column_with_format("Blank ratio", TableFormat::Right, move |x: &V| {
+            Percent::of_lines(info(x), info(x).empty, info(x).total)
+        })
+        .build()
+}
+
+// Lines per file, for tables with several files per row
+pub fn with_average_lines<V: 'static, K>(
+    descriptor: TableDescriptor<V, K>,
+    info: fn(&V) -> &FileInfo,
+) -> TableDescriptor<V, K> {
+    descriptor
+        .into_builder()
+        .computed_column_with_format("Lines per file", TableFormat::Right, move |x: &V| {
+            let info = info(x);
+            match (info.textual, info.file_count) {
+                (false, _) | (_, 0) => "-".to_string(),
+                (true, files) => format!("{:.1}", info.total as f64 / files as f64),
+            }
+        })
+        .build()
+}
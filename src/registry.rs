@@ -0,0 +1,121 @@
+use std::{
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use crate::{language::Language, line_kind::LineKindEstimator};
+
+// Estimators carry state for a single file, so what gets registered is a way to make new ones
+pub type EstimatorFactory = Arc<dyn Fn() -> Box<dyn LineKindEstimator + Send> + Send + Sync>;
+
+struct CustomLanguage {
+    language: Language,
+    extensions: Vec<String>,
+    filenames: Vec<String>,
+}
+
+struct Registry {
+    languages: Vec<CustomLanguage>,
+    estimators: Vec<(Language, EstimatorFactory)>,
+}
+
+static REGISTRY: RwLock<Registry> = RwLock::new(Registry {
+    languages: Vec::new(),
+    estimators: Vec::new(),
+});
+
+fn factory<E>(make_estimator: impl Fn() -> E + Send + Sync + 'static) -> EstimatorFactory
+where
+    E: LineKindEstimator + Send + 'static,
+{
+    Arc::new(move || Box::new(make_estimator()))
+}
+
+// Makes a new language known to `identify`, for files with one of the given extensions or names.
+// Files of this language are classified by the estimators made by `make_estimator`.
+pub fn register_language<E>(
+    name: &'static str,
+    extensions: &[&str],
+    filenames: &[&str],
+    make_estimator: impl Fn() -> E + Send + Sync + 'static,
+) -> Language
+where
+    E: LineKindEstimator + Send + 'static,
+{
+    let language = Language::Custom(name);
+    let mut registry = REGISTRY.write().unwrap_or_else(|err| err.into_inner());
+    registry
+        .languages
+        .retain(|custom| custom.language != language);
+    registry.languages.push(CustomLanguage {
+        language,
+        extensions: extensions.iter().map(|x| x.to_lowercase()).collect(),
+        filenames: filenames.iter().map(|x| x.to_string()).collect(),
+    });
+    drop(registry);
+
+    register_estimator(language, make_estimator);
+    language
+}
+
+// Overrides the estimator used for `language`, built-in or not
+pub fn register_estimator<E>(
+    language: Language,
+    make_estimator: impl Fn() -> E + Send + Sync + 'static,
+) where
+    E: LineKindEstimator + Send + 'static,
+{
+    let mut registry = REGISTRY.write().unwrap_or_else(|err| err.into_inner());
+    registry.estimators.retain(|(l, _)| *l != language);
+    registry
+        .estimators
+        .push((language, factory(make_estimator)));
+}
+
+// The language registered under this name, whose name already lives forever
+#[cfg(feature = "serde")]
+pub(crate) fn registered_language(name: &str) -> Option<Language> {
+    REGISTRY
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .languages
+        .iter()
+        .map(|custom| custom.language)
+        .find(|language| language.id() == name)
+}
+
+pub(crate) fn custom_language(path: &Path) -> Option<Language> {
+    let registry = REGISTRY.read().unwrap_or_else(|err| err.into_inner());
+    if registry.languages.is_empty() {
+        return None;
+    }
+
+    let filename = path.file_name().and_then(|x| x.to_str());
+    let extension = path
+        .extension()
+        .and_then(|x| x.to_str())
+        .map(|x| x.to_lowercase());
+    registry
+        .languages
+        .iter()
+        .find(|custom| {
+            filename.is_some_and(|f| custom.filenames.iter().any(|x| x == f))
+                || extension
+                    .as_ref()
+                    .is_some_and(|e| custom.extensions.contains(e))
+        })
+        .map(|custom| custom.language)
+}
+
+pub(crate) fn make_registered_estimator(
+    language: Language,
+) -> Option<Box<dyn LineKindEstimator + Send>> {
+    let factory = REGISTRY
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .estimators
+        .iter()
+        .find(|(l, _)| *l == language)
+        .map(|(_, factory)| factory.clone())?;
+    Some(factory())
+}
@@ -0,0 +1,148 @@
+use std::{cmp::Ordering, fmt::Display};
+
+use loc::FileInfo;
+
+use crate::errors::UsageError;
+
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    Code,
+    Comments,
+    Empty,
+    Total,
+    Files,
+    Language,
+    // The file, or what the row is about when it is not a file: its language or author
+    File,
+    Pct,
+    Density,
+    Blank,
+    Average,
+}
+
+impl Display for SortKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SortKey::Code => "code",
+            SortKey::Comments => "comments",
+            SortKey::Empty => "empty",
+            SortKey::Total => "total",
+            SortKey::Files => "files",
+            SortKey::Language => "language",
+            SortKey::File => "file",
+            SortKey::Pct => "pct",
+            SortKey::Density => "density",
+            SortKey::Blank => "blank",
+            SortKey::Average => "average",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(clap::Args, Debug)]
+pub struct SortArgs {
+    /// Sort by these columns, the next ones break the ties of the previous ones. Names go in
+    /// alphabetical order, numbers from the largest.
+    #[arg(
+        short,
+        long = "sort-by",
+        visible_alias = "sort",
+        value_delimiter = ',',
+        default_value = "code"
+    )]
+    sort: Vec<SortKey>,
+    /// Reverse the order of the rows, the total stays last
+    #[arg(short, long)]
+    reverse: bool,
+}
+
+// What a row is sorted on
+pub trait Sortable {
+    // The file, language or author of the row
+    fn name(&self) -> String;
+    fn language(&self) -> String;
+    // The value of a numeric key, None when the rows have no such column
+    fn number(&self, key: SortKey) -> Option<f64>;
+}
+
+// The numeric keys of the rows made of counts
+pub fn info_number(info: &FileInfo, key: SortKey) -> Option<f64> {
+    let ratio = |part: usize, whole: usize| match whole {
+        0 => 0.,
+        whole => part as f64 / whole as f64,
+    };
+    let number = match key {
+        // The share of the total code follows the code
+        SortKey::Code | SortKey::Pct => info.code as f64,
+        SortKey::Comments => info.comments as f64,
+        SortKey::Empty => info.empty as f64,
+        SortKey::Total => info.total as f64,
+        SortKey::Files => info.file_count as f64,
+        SortKey::Density => ratio(info.comments, info.code + info.comments),
+        SortKey::Blank => ratio(info.empty, info.total),
+        SortKey::Average => ratio(info.total, info.file_count),
+        SortKey::Language | SortKey::File => return None,
+    };
+    Some(number)
+}
+
+// A key of a row, computed once before sorting: the names are built on every call
+enum Value {
+    Text(String),
+    Number(f64),
+}
+
+fn value(row: &impl Sortable, key: SortKey) -> Value {
+    match key {
+        SortKey::Language => Value::Text(row.language()),
+        SortKey::File => Value::Text(row.name()),
+        key => Value::Number(row.number(key).unwrap_or_default()),
+    }
+}
+
+fn compare(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Text(a), Value::Text(b)) => a.cmp(b),
+        // Biggest first
+        (Value::Number(a), Value::Number(b)) => b.total_cmp(a),
+        // Values of the same key are of the same kind
+        _ => Ordering::Equal,
+    }
+}
+
+impl SortArgs {
+    pub fn sort<R: Sortable>(&self, rows: &mut Vec<R>) -> anyhow::Result<()> {
+        for key in &self.sort {
+            let numeric = !matches!(key, SortKey::Language | SortKey::File);
+            if numeric && rows.iter().any(|row| row.number(*key).is_none()) {
+                anyhow::bail!(UsageError(format!("Cannot sort by {key} here")));
+            }
+        }
+
+        let mut decorated: Vec<_> = rows
+            .drain(..)
+            .map(|row| {
+                let values: Vec<_> = self.sort.iter().map(|key| value(&row, *key)).collect();
+                (values, row.name(), row)
+            })
+            .collect();
+        decorated.sort_by(|(a_values, a_name, _), (b_values, b_name, _)| {
+            let ordering = a_values
+                .iter()
+                .zip(b_values)
+                .fold(Ordering::Equal, |ordering, (a, b)| {
+                    ordering.then_with(|| compare(a, b))
+                })
+                // The same counts always come in the same order
+                .then_with(|| a_name.cmp(b_name));
+            match self.reverse {
+                true => ordering.reverse(),
+                false => ordering,
+            }
+        });
+        rows.extend(decorated.into_iter().map(|(_, _, row)| row));
+        Ok(())
+    }
+}
@@ -15,24 +15,26 @@ const BOX_CROSS_UP: &str = "─┴─";
 const BOX_CROSS_RIGHT_UP: &str = "─┘";
 
 // Display tables, the wanky way
-pub struct TableWrapper<Tbl, T, It: Iterator<Item = T>> {
+pub struct TableWrapper<K, V, It: Iterator<Item = (K, V)>> {
+    descriptor: TableDescriptor<V, K>,
     data: std::cell::Cell<Option<It>>,
-    phantom: std::marker::PhantomData<(Tbl, T)>,
 }
 
-impl<T, It: Iterator<Item = T>> TableWrapper<(), T, It> {
-    pub fn new<Tbl>(data: It) -> TableWrapper<Tbl, T, It> {
+impl<K, V, It: Iterator<Item = (K, V)>> TableWrapper<K, V, It> {
+    pub fn new<Tbl: Table<Key = K, Value = V>>(data: It) -> Self {
+        Self::with_descriptor(Tbl::describe(), data)
+    }
+
+    // For columns only known at runtime
+    pub fn with_descriptor(descriptor: TableDescriptor<V, K>, data: It) -> Self {
         TableWrapper {
+            descriptor,
             data: std::cell::Cell::new(Some(data)),
-            phantom: std::marker::PhantomData,
         }
     }
 }
 
-impl<Tbl, U, V, It: Iterator<Item = (U, V)>> Display for TableWrapper<Tbl, (U, V), It>
-where
-    Tbl: Table<Key = U, Value = V>,
-{
+impl<K, V, It: Iterator<Item = (K, V)>> Display for TableWrapper<K, V, It> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         fn line_sep(
             pads: &[usize],
@@ -77,7 +79,7 @@ where
                 TableFormat::Right => write!(f, "{: >1$}", col, pad),
             }
         }
-        let table_descriptor = Tbl::describe();
+        let table_descriptor = &self.descriptor;
 
         let min_pad = 5;
         let out = std::cell::Cell::new(None);
@@ -183,14 +185,42 @@ pub struct TableDescriptorBuilder<T, Key> {
     key: TableEntry<Key>,
 }
 
-struct Lens<T>(Box<dyn for<'a> Fn(&'a T) -> &'a dyn Display>);
+// Borrowed from the row, or computed from it
+type Getter<T> = dyn for<'a> Fn(&'a T) -> Box<dyn Display + 'a>;
+struct Lens<T>(Box<Getter<T>>);
 
 impl<T> Lens<T> {
-    pub fn call<'s>(&self, t: &'s T) -> &'s dyn Display {
+    pub fn call<'s>(&self, t: &'s T) -> Box<dyn Display + 's> {
         (self.0)(t)
     }
 }
 
+impl<T, Key> TableDescriptor<T, Key> {
+    // To add columns to a table
+    pub fn into_builder(self) -> TableDescriptorBuilder<T, Key> {
+        TableDescriptorBuilder {
+            v: self.v,
+            key: self.key,
+        }
+    }
+
+    // Only the columns with the given names, in that order, after the key column. Fails with the
+    // first name that is not a column.
+    pub fn select(self, names: &[&str]) -> Result<Self, String> {
+        let mut entries: Vec<_> = self.v.into_iter().map(Some).collect();
+        let mut v = vec![];
+        for name in names {
+            let entry = entries
+                .iter_mut()
+                .find(|entry| entry.as_ref().is_some_and(|entry| entry.name == *name))
+                .and_then(Option::take)
+                .ok_or_else(|| name.to_string())?;
+            v.push(entry);
+        }
+        Ok(Self { v, key: self.key })
+    }
+}
+
 impl<T, Key> TableDescriptorBuilder<T, Key> {
     pub fn column_with_format(
         mut self,
@@ -201,7 +231,22 @@ impl<T, Key> TableDescriptorBuilder<T, Key> {
         self.v.push(TableEntry {
             name,
             format,
-            disp: Lens(Box::new(getter)),
+            disp: Lens(Box::new(move |x| Box::new(getter(x)))),
+        });
+        self
+    }
+
+    // A column of values that are not in the rows, computed from them
+    pub fn computed_column_with_format<D: Display + 'static>(
+        mut self,
+        name: &'static str,
+        format: TableFormat,
+        getter: impl (Fn(&T) -> D) + 'static,
+    ) -> Self {
+        self.v.push(TableEntry {
+            name,
+            format,
+            disp: Lens(Box::new(move |x| Box::new(getter(x)))),
         });
         self
     }
@@ -230,7 +275,7 @@ impl<T, Key> TableDescriptorBuilder<T, Key> {
             key: TableEntry {
                 name,
                 format,
-                disp: Lens(Box::new(getter)),
+                disp: Lens(Box::new(move |x| Box::new(getter(x)))),
             },
         }
     }